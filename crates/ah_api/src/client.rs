@@ -1,6 +1,10 @@
 use url::Url;
 
-use crate::{global_search::SearchResponse, product::ProductResponse, search::SearchResults};
+use crate::{
+    global_search::SearchResponse,
+    product::{Product, ProductResponse},
+    search::SearchResults,
+};
 
 #[derive(Clone)]
 pub struct AHClient {
@@ -50,26 +54,126 @@ impl AHClient {
         self.fetch(url).await?.json::<SearchResponse>().await
     }
 
+    /// Search for products, `page` is zero-based and `limit` is the size of a page.
     pub async fn search_products(
         &self,
-        query: &String,
+        query: &str,
         limit: usize,
+        page: usize,
     ) -> Result<SearchResults, reqwest::Error> {
         let base_url = "https://www.ah.nl/zoeken/api/products/search";
         let mut url = Url::parse(base_url).unwrap();
         url.query_pairs_mut()
-            .append_pair("query", &query)
-            .append_pair("size", limit.to_string().as_str());
+            .append_pair("query", query)
+            .append_pair("size", limit.to_string().as_str())
+            .append_pair("page", page.to_string().as_str());
         log::info!("searching: {}", url);
 
         self.fetch(url).await?.json::<SearchResults>().await
     }
 
+    /// Find the product with the given GTIN, the number encoded in its barcode. The
+    /// search endpoint matches GTINs, but results are checked since a query made of
+    /// digits can match other products as well.
+    pub async fn get_product_by_gtin(&self, gtin: &str) -> Result<Option<Product>, reqwest::Error> {
+        let gtin_number = match gtin.parse::<i64>() {
+            Ok(gtin_number) => gtin_number,
+            Err(_) => return Ok(None),
+        };
+        let results = self.search_products(gtin, 10, 0).await?;
+        Ok(results
+            .cards
+            .into_iter()
+            .flat_map(|card| card.products)
+            .find(|product| product.gtins.contains(&gtin_number)))
+    }
+
+    /// Search for products that are currently on discount. The bonus filter of the
+    /// search endpoint is not always reliable, so results without a discount are
+    /// filtered out as well.
+    async fn search_bonus(
+        &self,
+        filters: &[(&str, &str)],
+        limit: usize,
+    ) -> Result<Vec<Product>, reqwest::Error> {
+        let base_url = "https://www.ah.nl/zoeken/api/products/search";
+        let mut url = Url::parse(base_url).unwrap();
+        url.query_pairs_mut()
+            .extend_pairs(filters)
+            .append_pair("properties", "bonus")
+            .append_pair("size", limit.to_string().as_str());
+        log::info!("searching bonus products: {}", url);
+
+        let results = self.fetch(url).await?.json::<SearchResults>().await?;
+        Ok(results
+            .cards
+            .into_iter()
+            .flat_map(|card| card.products)
+            .filter(|product| product.is_on_discount())
+            .collect())
+    }
+
+    /// Search for products matching `query` that are currently on discount.
+    pub async fn search_bonus_products(
+        &self,
+        query: &str,
+        limit: usize,
+    ) -> Result<Vec<Product>, reqwest::Error> {
+        self.search_bonus(&[("query", query)], limit).await
+    }
+
+    /// Search for products of the given brand that are currently on discount.
+    pub async fn search_bonus_products_by_brand(
+        &self,
+        brand: &str,
+        limit: usize,
+    ) -> Result<Vec<Product>, reqwest::Error> {
+        let products = self.search_bonus(&[("query", brand)], limit).await?;
+        Ok(products
+            .into_iter()
+            .filter(|product| product.brand.eq_ignore_ascii_case(brand))
+            .collect())
+    }
+
+    /// Search for products in the given taxonomy, e.g. "Koffie", that are currently
+    /// on discount.
+    pub async fn search_bonus_products_in_taxonomy(
+        &self,
+        taxonomy_id: i64,
+        limit: usize,
+    ) -> Result<Vec<Product>, reqwest::Error> {
+        let taxonomy_id = taxonomy_id.to_string();
+        self.search_bonus(&[("taxonomy", taxonomy_id.as_str())], limit)
+            .await
+    }
+
     pub async fn get_product(&self, product_id: &str) -> Result<ProductResponse, reqwest::Error> {
+        let url = Self::product_url(product_id);
+        log::info!("Fetching product: {}", url);
+        self.fetch(url).await?.json::<ProductResponse>().await
+    }
+
+    /// Fetch a product by its webshop ID. Returns `None` if AH doesn't know the
+    /// product, other failures are errors.
+    pub async fn find_product(&self, product_id: &str) -> Result<Option<Product>, reqwest::Error> {
+        let url = Self::product_url(product_id);
+        log::info!("Fetching product: {}", url);
+
+        let response = self.fetch(url).await?;
+        if response.status() == reqwest::StatusCode::NOT_FOUND {
+            return Ok(None);
+        }
+        let response = response
+            .error_for_status()?
+            .json::<ProductResponse>()
+            .await?;
+        Ok(response.card.products.into_iter().next())
+    }
+
+    fn product_url(product_id: &str) -> Url {
         let base_url = "https://www.ah.nl/zoeken/api/products/product";
         let mut url = Url::parse(base_url).unwrap();
         url.query_pairs_mut().append_pair("webshopId", product_id);
-        log::info!("Fetching product: {}", url);
-        self.fetch(url).await?.json::<ProductResponse>().await
+        url
     }
 }
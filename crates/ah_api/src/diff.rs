@@ -0,0 +1,270 @@
+use url::Url;
+
+use crate::product::{Discount, Product};
+
+/// A field that has a different value in the newer snapshot of a product.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Change<T> {
+    pub old: T,
+    pub new: T,
+}
+
+impl<T: PartialEq + Clone> Change<T> {
+    /// Returns `Some` only if the two values differ
+    fn between(old: &T, new: &T) -> Option<Self> {
+        if old == new {
+            None
+        } else {
+            Some(Self {
+                old: old.clone(),
+                new: new.clone(),
+            })
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum DiscountChange {
+    /// The product went on discount
+    Started(Discount),
+    /// The product is no longer on discount
+    Ended(Discount),
+    /// The product is still on discount, but the discount itself is different,
+    /// e.g. a new bonus period started right after the previous one
+    Changed(Change<Discount>),
+}
+
+/// Differences between two snapshots of the same product, e.g. the one fetched
+/// last week and the one fetched today. Fields that did not change are `None`.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ProductDiff {
+    pub price: Option<Change<f64>>,
+    pub unit_price: Option<Change<f64>>,
+    pub discount: Option<DiscountChange>,
+    pub shield_text: Option<Change<Option<String>>>,
+    pub title: Option<Change<String>>,
+    pub brand: Option<Change<String>>,
+    pub image: Option<Change<Option<Url>>>,
+    pub available_online: Option<Change<bool>>,
+    pub orderable: Option<Change<bool>>,
+    pub unit_size: Option<Change<String>>,
+}
+
+impl ProductDiff {
+    pub fn new(old: &Product, new: &Product) -> Self {
+        let discount = match (&old.discount, &new.discount) {
+            (None, Some(discount)) => Some(DiscountChange::Started(discount.clone())),
+            (Some(discount), None) => Some(DiscountChange::Ended(discount.clone())),
+            (Some(old), Some(new)) => Change::between(old, new).map(DiscountChange::Changed),
+            (None, None) => None,
+        };
+        let unit_price = match (&old.price.unit_info, &new.price.unit_info) {
+            (Some(old), Some(new)) => Change::between(&old.price, &new.price),
+            _ => None,
+        };
+
+        Self {
+            price: Change::between(&old.price.now, &new.price.now),
+            unit_price,
+            discount,
+            shield_text: Change::between(
+                &old.get_discount_text().cloned(),
+                &new.get_discount_text().cloned(),
+            ),
+            title: Change::between(&old.title, &new.title),
+            brand: Change::between(&old.brand, &new.brand),
+            image: Change::between(
+                &old.images.last().map(|image| image.url.clone()),
+                &new.images.last().map(|image| image.url.clone()),
+            ),
+            available_online: Change::between(&old.available_online, &new.available_online),
+            orderable: Change::between(&old.orderable, &new.orderable),
+            unit_size: Change::between(&old.price.unit_size, &new.price.unit_size),
+        }
+    }
+
+    /// Returns `true` if nothing changed between the two snapshots
+    pub fn is_empty(&self) -> bool {
+        *self == Self::default()
+    }
+
+    pub fn price_increased(&self) -> bool {
+        matches!(&self.price, Some(change) if change.new > change.old)
+    }
+
+    pub fn price_decreased(&self) -> bool {
+        matches!(&self.price, Some(change) if change.new < change.old)
+    }
+
+    /// Returns `true` if the product can be ordered again after being unavailable
+    pub fn is_back_in_stock(&self) -> bool {
+        matches!(&self.orderable, Some(change) if change.new)
+    }
+
+    /// Returns `true` if the pack size changed and the price per unit (kg, litre, ...)
+    /// went up as a result, e.g. same price for a smaller pack.
+    pub fn is_shrinkflation(&self) -> bool {
+        self.unit_size.is_some()
+            && matches!(&self.unit_price, Some(change) if change.new > change.old)
+    }
+}
+
+impl Product {
+    /// Compares this product with a newer snapshot of it
+    pub fn diff(&self, newer: &Product) -> ProductDiff {
+        ProductDiff::new(self, newer)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::product::{Control, Price, Properties, UnitInfo};
+
+    fn product() -> Product {
+        Product {
+            id: 1,
+            control: Control::default(),
+            title: "Halfvolle melk".to_string(),
+            link: "/producten/product/wi1/halfvolle-melk".to_string(),
+            available_online: true,
+            orderable: true,
+            property_icons: vec![],
+            images: vec![],
+            shield: None,
+            price: Price {
+                unit_info: Some(UnitInfo {
+                    price: 1.19,
+                    description: "Prijs per L".to_string(),
+                }),
+                theme: None,
+                now: 1.19,
+                was: None,
+                unit_size: "1 l".to_string(),
+            },
+            discount: None,
+            item_catalog_id: None,
+            brand: "AH".to_string(),
+            category: "Zuivel".to_string(),
+            theme: "ah".to_string(),
+            hq_id: 1,
+            gtins: vec![],
+            summary: String::new(),
+            description_full: String::new(),
+            taxonomy_id: None,
+            taxonomies: vec![],
+            contribution_margin: None,
+            properties: Properties::default(),
+        }
+    }
+
+    fn discount(end_date: &str) -> Discount {
+        Discount {
+            bonus_type: "BONUS".to_string(),
+            start_date: "2026-10-19".to_string(),
+            end_date: end_date.to_string(),
+            ..Discount::default()
+        }
+    }
+
+    #[test]
+    fn identical_products_have_no_changes() {
+        let diff = product().diff(&product());
+        assert!(diff.is_empty());
+        assert!(!diff.price_increased());
+        assert!(!diff.price_decreased());
+        assert!(!diff.is_back_in_stock());
+        assert!(!diff.is_shrinkflation());
+    }
+
+    #[test]
+    fn discount_started() {
+        let mut newer = product();
+        newer.discount = Some(discount("2026-10-25"));
+        assert_eq!(
+            product().diff(&newer).discount,
+            Some(DiscountChange::Started(discount("2026-10-25")))
+        );
+    }
+
+    #[test]
+    fn discount_ended() {
+        let mut older = product();
+        older.discount = Some(discount("2026-10-25"));
+        assert_eq!(
+            older.diff(&product()).discount,
+            Some(DiscountChange::Ended(discount("2026-10-25")))
+        );
+    }
+
+    #[test]
+    fn discount_changed() {
+        let mut older = product();
+        older.discount = Some(discount("2026-10-25"));
+        let mut newer = product();
+        newer.discount = Some(discount("2026-11-01"));
+        assert_eq!(
+            older.diff(&newer).discount,
+            Some(DiscountChange::Changed(Change {
+                old: discount("2026-10-25"),
+                new: discount("2026-11-01"),
+            }))
+        );
+    }
+
+    #[test]
+    fn price_increase() {
+        let mut newer = product();
+        newer.price.now = 1.29;
+        let diff = product().diff(&newer);
+        assert!(diff.price_increased());
+        assert!(!diff.price_decreased());
+        assert!(newer.diff(&product()).price_decreased());
+    }
+
+    #[test]
+    fn back_in_stock() {
+        let mut older = product();
+        older.orderable = false;
+        assert!(older.diff(&product()).is_back_in_stock());
+        assert!(!product().diff(&older).is_back_in_stock());
+    }
+
+    #[test]
+    fn same_price_for_smaller_pack_is_shrinkflation() {
+        let mut newer = product();
+        newer.price.unit_size = "900 ml".to_string();
+        newer.price.unit_info = Some(UnitInfo {
+            price: 1.32,
+            description: "Prijs per L".to_string(),
+        });
+        let diff = product().diff(&newer);
+        assert!(diff.is_shrinkflation());
+        assert!(diff.price.is_none());
+    }
+
+    #[test]
+    fn unit_price_up_without_new_pack_size_is_no_shrinkflation() {
+        let mut newer = product();
+        newer.price.now = 1.29;
+        newer.price.unit_info = Some(UnitInfo {
+            price: 1.29,
+            description: "Prijs per L".to_string(),
+        });
+        let diff = product().diff(&newer);
+        assert!(!diff.is_shrinkflation());
+        assert!(diff.price_increased());
+    }
+
+    #[test]
+    fn bigger_pack_for_lower_unit_price_is_no_shrinkflation() {
+        let mut newer = product();
+        newer.price.now = 2.19;
+        newer.price.unit_size = "2 l".to_string();
+        newer.price.unit_info = Some(UnitInfo {
+            price: 1.10,
+            description: "Prijs per L".to_string(),
+        });
+        assert!(!product().diff(&newer).is_shrinkflation());
+    }
+}
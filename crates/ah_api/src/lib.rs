@@ -1,4 +1,5 @@
 pub mod client;
+pub mod diff;
 pub mod global_search;
 pub mod product;
 pub mod search;
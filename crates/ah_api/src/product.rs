@@ -265,9 +265,32 @@ impl Product {
         }
     }
 
+    /// Returns the most specific taxonomy the product belongs to, e.g. "Koffiebonen"
+    /// rather than "Koffie, thee"
+    pub fn get_taxonomy(&self) -> Option<&Taxonomy> {
+        self.taxonomies
+            .iter()
+            .find(|taxonomy| Some(taxonomy.id) == self.taxonomy_id)
+            .or_else(|| self.taxonomies.iter().max_by_key(|taxonomy| taxonomy.level))
+    }
+
     /// Returns the price of the product in cents so it can be stored in the database
     /// as an integer
     pub fn get_price_for_db(&self) -> u32 {
-        (&self.price.now * 100.0) as u32
+        (&self.price.now * 100.0).round() as u32
+    }
+
+    /// Returns the regular price of a discounted product in cents, if AH provides one
+    pub fn get_price_was_for_db(&self) -> Option<u32> {
+        self.price.was.map(|was| (was * 100.0).round() as u32)
+    }
+
+    /// Returns the price per unit (kg, litre, ...) of the product in cents, if AH
+    /// provides one
+    pub fn get_unit_price_for_db(&self) -> Option<u32> {
+        self.price
+            .unit_info
+            .as_ref()
+            .map(|unit_info| (unit_info.price * 100.0).round() as u32)
     }
 }
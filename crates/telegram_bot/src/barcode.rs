@@ -0,0 +1,33 @@
+use std::fmt;
+
+use rxing::{helpers::detect_in_luma, BarcodeFormat};
+
+#[derive(Debug)]
+pub enum BarcodeError {
+    /// The photo could not be decoded as an image
+    Image(image::ImageError),
+    /// There is no readable EAN-13 barcode in the photo
+    NotFound,
+}
+
+impl fmt::Display for BarcodeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            BarcodeError::Image(e) => write!(f, "failed to read photo: {}", e),
+            BarcodeError::NotFound => write!(f, "no barcode found"),
+        }
+    }
+}
+
+/// Decodes the EAN-13 barcode in a photo, e.g. a JPEG sent to the bot, and returns
+/// its 13 digits. UPC-A barcodes are returned as EAN-13 with a leading zero.
+pub fn decode_ean13(photo: &[u8]) -> Result<String, BarcodeError> {
+    let image = image::load_from_memory(photo)
+        .map_err(BarcodeError::Image)?
+        .into_luma8();
+    let (width, height) = image.dimensions();
+
+    let result = detect_in_luma(image.into_raw(), width, height, Some(BarcodeFormat::EAN_13))
+        .map_err(|_| BarcodeError::NotFound)?;
+    Ok(result.getText().to_string())
+}
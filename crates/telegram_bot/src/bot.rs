@@ -1,22 +1,51 @@
-use std::{collections::HashSet, str::FromStr};
+use std::{collections::HashSet, sync::Arc};
 
-use ah_api::client::AHClient;
+use ah_api::{client::AHClient, product::Product, search::SearchResults};
 
 use clap::Parser;
 use sqlx::SqlitePool;
-use telegram_bot::db;
+use telegram_bot::{
+    barcode,
+    callback::{is_mute_button, mute_buttons, Callback, ListSort, WatchKind},
+    chart, db,
+    dialogue::{BotDialogue, SqliteDialogueStorage, State},
+    errors::{HandlerError, HandlerResult, UpdateError, UpdateResult},
+    format::{format_price, split_message},
+    prediction,
+    settings::{weekday_abbreviation, ChatSettings, WEEKDAYS},
+    target::Target,
+};
 use teloxide::{
     adaptors::{throttle::Limits, Throttle},
+    net::Download,
     prelude::*,
-    types::{InlineKeyboardButton, InlineKeyboardMarkup, InputFile},
-    utils::command::BotCommands,
+    types::{
+        InlineKeyboardButton, InlineKeyboardMarkup, InlineQueryResult, InlineQueryResultPhoto,
+        InputFile, MessageId, ParseMode, PhotoSize,
+    },
+    utils::{command::BotCommands, markdown::escape},
+    ApiError, RequestError,
 };
+use time::{Date, PrimitiveDateTime};
 
 #[derive(Parser, Debug)]
 #[command(author, version, about, long_about = None)]
 pub struct Cli {
     #[arg(short = 'd', long = "db-url", default_value = "sqlite:ah_bonus.db")]
     pub db_url: String,
+    /// Number of products shown on every page of search results
+    #[arg(long = "search-page-size", default_value_t = 5)]
+    pub search_page_size: usize,
+    /// Number of products shown on every page of `/list`
+    #[arg(long = "list-page-size", default_value_t = 10)]
+    pub list_page_size: usize,
+}
+
+/// Settings shared with all handlers
+#[derive(Clone)]
+struct Config {
+    search_page_size: usize,
+    list_page_size: usize,
 }
 
 #[derive(BotCommands, Clone)]
@@ -32,9 +61,35 @@ enum Command {
     #[command(description = "Search for a product.")]
     Search(String),
     #[command(
-        description = "Get a list of all products you're tracking. The command also allows you to stop tracking a product."
+        description = "Get a list of all products you're tracking with their price and bonus status. The list also allows you to stop tracking or mute all products."
     )]
     List,
+    #[command(
+        description = "Only get notified when a tracked product reaches a target, e.g. `/target 12345 2.50` or `/target 12345 25%`. Use `off` to get notified of any discount."
+    )]
+    Target(String),
+    #[command(description = "Get notified when any product matching a search goes on bonus.")]
+    Watch(String),
+    #[command(
+        description = "Get a list of your watches and followed brands and categories. The command also allows you to remove them."
+    )]
+    Watches,
+    #[command(
+        description = "Show a chart of the price history of a product, e.g. `/history 12345`."
+    )]
+    History(String),
+    #[command(
+        description = "Predict the chance your tracked products will be on bonus in the coming weeks."
+    )]
+    Predict,
+    #[command(description = "List products that got smaller without getting cheaper.")]
+    Shrinkflation,
+    #[command(description = "Cancel the current operation, e.g. setting a custom target.")]
+    Cancel,
+    #[command(
+        description = "Choose the days and time to get notified, whether to get a message when nothing is on sale, reminders on the last day of a discount and all discounts in one message."
+    )]
+    Settings,
 }
 
 #[tokio::main]
@@ -62,38 +117,76 @@ async fn main() {
         .await
         .expect("Failed to set commands");
 
-    let command_handler = Update::filter_message()
+    let command_handler = dptree::entry()
         .filter_command::<Command>()
         .endpoint(commands_handler);
+    let receive_target_handler =
+        dptree::case![State::ReceiveTarget { product_id }].endpoint(receive_target_handler);
+    let product_link_handler =
+        dptree::filter_map(|msg: Message| msg.text().and_then(find_webshop_id))
+            .endpoint(product_link_handler);
+    let barcode_handler =
+        dptree::filter_map(|msg: Message| msg.photo().and_then(|sizes| sizes.last()).cloned())
+            .endpoint(barcode_handler);
+    let migration_handler = dptree::filter_map(|msg: Message| msg.migrate_to_chat_id().copied())
+        .endpoint(migration_handler);
+    let message_handler = Update::filter_message()
+        .branch(migration_handler)
+        .enter_dialogue::<Message, SqliteDialogueStorage, State>()
+        .branch(command_handler)
+        .branch(receive_target_handler)
+        .branch(product_link_handler)
+        .branch(barcode_handler);
     let callback_query_handler = Update::filter_callback_query().endpoint(callback_query_handler);
+    let inline_query_handler = Update::filter_inline_query().endpoint(inline_query_handler);
+    let chat_member_handler = Update::filter_my_chat_member().endpoint(chat_member_handler);
 
     let handler = dptree::entry()
-        .branch(command_handler)
-        .branch(callback_query_handler);
+        .branch(message_handler)
+        .branch(callback_query_handler)
+        .branch(inline_query_handler)
+        .branch(chat_member_handler);
+
+    let config = Config {
+        search_page_size: args.search_page_size,
+        list_page_size: args.list_page_size,
+    };
+
+    let dialogue_storage = SqliteDialogueStorage::new(pool.clone());
 
     Dispatcher::builder(bot, handler)
-        .dependencies(dptree::deps![pool, ah_client])
+        .dependencies(dptree::deps![pool, ah_client, config, dialogue_storage])
+        .error_handler(Arc::new(|e: UpdateError| async move {
+            log::error!("Failed to handle update: {}", e);
+        }))
         .enable_ctrlc_handler()
         .build()
         .dispatch()
         .await;
 }
 
-enum Action {
-    TrackProduct = 0,
-    StopTrackingProduct = 1,
+/// Marks chats inactive when the bot is blocked or removed, and active again when
+/// it is unblocked or added back, so the notifier doesn't keep sending to them.
+async fn chat_member_handler(update: ChatMemberUpdated, pool: SqlitePool) -> UpdateResult {
+    let active = update.new_chat_member.is_present();
+    log::info!(
+        "chat member: chat_id={} active={}",
+        update.chat.id.0,
+        active
+    );
+    db::set_chat_active(&pool, update.chat.id.0, active)
+        .await
+        .map_err(|e| HandlerError::from(e).in_update("chat member", Some(update.chat.id)))?;
+    Ok(())
 }
 
-impl FromStr for Action {
-    type Err = ();
-
-    fn from_str(s: &str) -> Result<Self, Self::Err> {
-        match s {
-            "0" => Ok(Action::TrackProduct),
-            "1" => Ok(Action::StopTrackingProduct),
-            _ => Err(()),
-        }
-    }
+/// Moves everything of a group to its new ID when it becomes a supergroup
+async fn migration_handler(msg: Message, new_chat_id: ChatId, pool: SqlitePool) -> UpdateResult {
+    log::info!("migrate chat: {} to {}", msg.chat.id.0, new_chat_id.0);
+    db::migrate_chat(&pool, msg.chat.id.0, new_chat_id.0)
+        .await
+        .map_err(|e| HandlerError::from(e).in_update("migration", Some(msg.chat.id)))?;
+    Ok(())
 }
 
 async fn callback_query_handler(
@@ -101,153 +194,640 @@ async fn callback_query_handler(
     q: CallbackQuery,
     pool: SqlitePool,
     ah_client: AHClient,
-) -> ResponseResult<()> {
-    let text = q.data;
-    if text.is_none() {
-        return Ok(());
+    config: Config,
+    dialogue_storage: Arc<SqliteDialogueStorage>,
+) -> UpdateResult {
+    let answer = handle_callback(&bot, &q, &pool, &ah_client, &config, dialogue_storage).await;
+
+    let request = bot.answer_callback_query(q.id.clone());
+    let request = match &answer {
+        Ok(Answer::Silent) => request,
+        Ok(Answer::Toast(text)) => request.text(text),
+        Ok(Answer::Alert(text)) => request.text(text).show_alert(true),
+        Err(e) => request
+            .text(
+                e.user_message()
+                    .unwrap_or("Something went wrong, try again later"),
+            )
+            .show_alert(true),
+    };
+    let answered = request.await;
+
+    let chat_id = q.message.as_ref().map(|message| message.chat().id);
+    answer.map_err(|e| e.in_update("callback query", chat_id))?;
+    answered.map_err(|e| HandlerError::from(e).in_update("callback query", chat_id))?;
+    Ok(())
+}
+
+/// Feedback shown after pressing a button. Every callback query has to be
+/// answered, otherwise clients keep showing the button as loading.
+enum Answer {
+    /// Nothing to show, e.g. the bot sends a message instead
+    Silent,
+    /// A notification that disappears after a few seconds
+    Toast(String),
+    /// A popup the user has to dismiss
+    Alert(String),
+}
+
+type CallbackResult = Result<Answer, HandlerError>;
+
+async fn handle_callback(
+    bot: &Throttle<Bot>,
+    q: &CallbackQuery,
+    pool: &SqlitePool,
+    ah_client: &AHClient,
+    config: &Config,
+    dialogue_storage: Arc<SqliteDialogueStorage>,
+) -> CallbackResult {
+    let callback = match q.data.as_deref().map(str::parse::<Callback>) {
+        Some(Ok(callback)) => callback,
+        Some(Err(e)) => {
+            log::info!("callback query: {} {:?}", e, q.data);
+            return Ok(Answer::Alert("This button has expired".to_string()));
+        }
+        None => return Ok(Answer::Silent),
+    };
+
+    // messages sent in inline mode belong to another chat, so the buttons only
+    // track products for the private chat of the user who pressed them
+    let Some(message) = q.regular_message() else {
+        let Some(inline_message_id) = &q.inline_message_id else {
+            return Ok(Answer::Alert("This button has expired".to_string()));
+        };
+        let button_message = ButtonMessage::Inline(inline_message_id.clone());
+        let chat_id = ChatId::from(q.from.id);
+        return match callback {
+            Callback::Track { product_id } => {
+                track_product(bot, chat_id, &button_message, pool, ah_client, product_id).await
+            }
+            Callback::StopTracking { product_id } => {
+                stop_tracking_product(bot, chat_id, &button_message, pool, product_id).await
+            }
+            _ => Ok(Answer::Silent),
+        };
+    };
+    let button_message = ButtonMessage::Chat(message.chat.id, message.id);
+    let dialogue = BotDialogue::new(dialogue_storage, message.chat.id);
+
+    match callback {
+        Callback::Track { product_id } => {
+            track_product(
+                bot,
+                message.chat.id,
+                &button_message,
+                pool,
+                ah_client,
+                product_id,
+            )
+            .await
+        }
+        Callback::StopTracking { product_id } => {
+            stop_tracking_product(bot, message.chat.id, &button_message, pool, product_id).await
+        }
+        Callback::SetTarget {
+            product_id,
+            min_discount,
+        } => {
+            let target = match min_discount {
+                0 => Target::AnyDiscount,
+                percentage => Target::MinDiscount(percentage),
+            };
+            set_target(bot, message, pool, product_id, target).await
+        }
+        Callback::Follow { kind, product_id } => {
+            follow(message, pool, ah_client, kind, product_id).await
+        }
+        Callback::RemoveWatch { kind, watch_id } => {
+            remove_watch(bot, message, pool, kind, watch_id).await
+        }
+        Callback::History { product_id } => {
+            send_price_history(bot, message.chat.id, pool, product_id).await?;
+            Ok(Answer::Silent)
+        }
+        Callback::ShowProduct { product_id } => {
+            let product_response = ah_client.get_product(&product_id.to_string()).await?;
+            let product = product_response
+                .card
+                .products
+                .first()
+                .ok_or(HandlerError::ProductNotFound)?;
+            send_product_card(bot, message.chat.id, pool, product).await?;
+            Ok(Answer::Silent)
+        }
+        Callback::SearchPage { search_id, page } => {
+            search_page(bot, message, pool, ah_client, config, search_id, page).await
+        }
+        Callback::CustomTarget { product_id } => {
+            start_custom_target(bot, message.chat.id, &dialogue, product_id).await
+        }
+        Callback::ConfirmTarget { product_id } => {
+            confirm_target(bot, message, pool, &dialogue, product_id).await
+        }
+        Callback::ListPage { sort, page } => {
+            list_page(bot, message, pool, config, sort, page).await
+        }
+        Callback::UntrackAll { sort, page } => untrack_all(bot, message, pool, sort, page).await,
+        Callback::ConfirmUntrackAll => confirm_untrack_all(bot, message, pool).await,
+        Callback::MuteAll { mute, sort, page } => {
+            mute_all(bot, message, pool, config, mute, sort, page).await
+        }
+        Callback::ToggleDay { day } => {
+            update_settings(bot, message, pool, |settings| settings.toggle_day(day)).await
+        }
+        Callback::SetNotificationHour { hour } => {
+            update_settings(bot, message, pool, |settings| {
+                settings.notification_hour = hour
+            })
+            .await
+        }
+        Callback::ToggleNothingOnSale => {
+            update_settings(bot, message, pool, |settings| {
+                settings.send_nothing_on_sale = !settings.send_nothing_on_sale
+            })
+            .await
+        }
+        Callback::ToggleLastDayReminder => {
+            update_settings(bot, message, pool, |settings| {
+                settings.last_day_reminder = !settings.last_day_reminder
+            })
+            .await
+        }
+        Callback::ToggleDigest => {
+            update_settings(bot, message, pool, |settings| {
+                settings.digest = !settings.digest
+            })
+            .await
+        }
+        Callback::Mute { product_id } => {
+            set_mute(bot, message, pool, product_id, false, None).await
+        }
+        Callback::Snooze { product_id, weeks } => {
+            let Some(until) = time::OffsetDateTime::now_utc()
+                .date()
+                .checked_add(time::Duration::weeks(weeks))
+            else {
+                return Ok(Answer::Alert("Failed to snooze notifications".to_string()));
+            };
+            set_mute(bot, message, pool, product_id, true, Some(until)).await
+        }
+        Callback::Unmute { product_id } => {
+            set_mute(bot, message, pool, product_id, true, None).await
+        }
+        Callback::CancelDialogue => {
+            cancel_dialogue(&dialogue).await;
+            bot.edit_message_text(message.chat.id, message.id, "Cancelled")
+                .await?;
+            Ok(Answer::Silent)
+        }
     }
-    let text = text.unwrap();
+}
 
-    let message = q.message;
-    if message.is_none() {
-        return Ok(());
+/// Tells the user their request failed, the error itself is logged by the
+/// dispatcher.
+async fn reply_error(bot: &Throttle<Bot>, chat_id: ChatId, result: HandlerResult) -> HandlerResult {
+    if let Some(text) = result.as_ref().err().and_then(HandlerError::user_message) {
+        if let Err(e) = bot.send_message(chat_id, text).await {
+            log::error!("Failed to reply with error to {}: {}", chat_id.0, e);
+        }
     }
-    let message = message.unwrap();
+    result
+}
 
-    let (action, product_id) = text.split_once(":").expect("Invalid callback query");
-    let parsed_action: Action = action.parse::<Action>().expect("Invalid action");
+/// A message with buttons, either sent by the bot to a chat or sent by a user in
+/// inline mode
+enum ButtonMessage {
+    Chat(ChatId, MessageId),
+    Inline(String),
+}
 
-    match parsed_action {
-        Action::TrackProduct => track_product(&bot, &message, &pool, &ah_client, product_id).await,
-        Action::StopTrackingProduct => {
-            stop_tracking_product(&bot, &message, &pool, product_id).await
+impl ButtonMessage {
+    async fn edit_keyboard(
+        &self,
+        bot: &Throttle<Bot>,
+        keyboard: InlineKeyboardMarkup,
+    ) -> HandlerResult {
+        match self {
+            ButtonMessage::Chat(chat_id, message_id) => {
+                bot.edit_message_reply_markup(*chat_id, *message_id)
+                    .reply_markup(keyboard)
+                    .await?;
+            }
+            ButtonMessage::Inline(inline_message_id) => {
+                bot.edit_message_reply_markup_inline(inline_message_id)
+                    .reply_markup(keyboard)
+                    .await?;
+            }
         }
+        Ok(())
     }
 }
 
-fn create_track_keyboard(product_id: i64) -> InlineKeyboardMarkup {
-    let button = InlineKeyboardButton::new(
-        "Track",
-        teloxide::types::InlineKeyboardButtonKind::CallbackData(format!(
-            "{}:{}",
-            Action::TrackProduct as u8,
-            product_id
-        )),
-    );
+/// Keyboard of the results of inline queries, other buttons of product cards need
+/// a chat to answer in.
+fn create_inline_keyboard(product_id: i64, tracked: bool) -> InlineKeyboardMarkup {
+    let button = if tracked {
+        Callback::StopTracking { product_id }.button("Stop tracking")
+    } else {
+        Callback::Track { product_id }.button("Track")
+    };
     InlineKeyboardMarkup::default().append_row(vec![button])
 }
 
+/// Number of products returned for an inline query
+const INLINE_QUERY_LIMIT: usize = 10;
+
+/// Answers inline queries, e.g. `@bot hagelslag`, with photos of the matching
+/// products that can be sent to any chat.
+async fn inline_query_handler(
+    bot: Throttle<Bot>,
+    q: InlineQuery,
+    ah_client: AHClient,
+) -> UpdateResult {
+    answer_inline_query(bot, q, ah_client)
+        .await
+        .map_err(|e| e.in_update("inline query", None))
+}
+
+async fn answer_inline_query(
+    bot: Throttle<Bot>,
+    q: InlineQuery,
+    ah_client: AHClient,
+) -> HandlerResult {
+    let query = q.query.trim().to_string();
+    if query.is_empty() {
+        bot.answer_inline_query(q.id, vec![]).await?;
+        return Ok(());
+    }
+    log::info!("inline query: query={}", query);
+
+    let search_results = ah_client
+        .search_products(&query, INLINE_QUERY_LIMIT, 0)
+        .await?;
+    let results = search_results
+        .cards
+        .iter()
+        .filter_map(|card| card.products.first())
+        .filter_map(|product| {
+            let image_url = product.images.last()?.url.clone();
+            let price = format!("€{} {}", product.price.now, product.price.unit_size);
+            let photo =
+                InlineQueryResultPhoto::new(product.id.to_string(), image_url.clone(), image_url)
+                    .title(product.title.clone())
+                    .description(price.clone())
+                    .caption(format!("{} - {}", product.title, price))
+                    .reply_markup(create_inline_keyboard(product.id, false));
+            Some(InlineQueryResult::Photo(photo))
+        })
+        .collect::<Vec<_>>();
+
+    bot.answer_inline_query(q.id, results).await?;
+    Ok(())
+}
+
+fn create_track_keyboard(product_id: i64) -> InlineKeyboardMarkup {
+    let button = Callback::Track { product_id }.button("Track");
+    InlineKeyboardMarkup::default()
+        .append_row(vec![button, create_history_button(product_id)])
+        .append_row(create_follow_buttons(product_id))
+}
+
 fn create_stop_track_keyboard(product_id: i64) -> InlineKeyboardMarkup {
-    let button = InlineKeyboardButton::new(
-        "Stop tracking",
-        teloxide::types::InlineKeyboardButtonKind::CallbackData(format!(
-            "{}:{}",
-            Action::StopTrackingProduct as u8,
-            product_id
-        )),
+    let button = Callback::StopTracking { product_id }.button("Stop tracking");
+    InlineKeyboardMarkup::default()
+        .append_row(vec![button, create_history_button(product_id)])
+        .append_row(create_follow_buttons(product_id))
+}
+
+fn create_history_button(product_id: i64) -> InlineKeyboardButton {
+    Callback::History { product_id }.button("History")
+}
+
+/// Buttons to get notified of any discount on the brand or category of a product
+fn create_follow_buttons(product_id: i64) -> Vec<InlineKeyboardButton> {
+    [
+        ("Follow brand", WatchKind::Brand),
+        ("Follow category", WatchKind::Taxonomy),
+    ]
+    .into_iter()
+    .map(|(text, kind)| Callback::Follow { kind, product_id }.button(text))
+    .collect()
+}
+
+/// Discount percentages offered as buttons after tracking a product
+const TARGET_PERCENTAGES: [i64; 2] = [25, 50];
+
+/// Keyboard shown after tracking a product, it allows to stop tracking it or to
+/// only get notified of larger discounts.
+fn create_tracked_keyboard(product_id: i64) -> InlineKeyboardMarkup {
+    let any_discount = Callback::SetTarget {
+        product_id,
+        min_discount: 0,
+    }
+    .button("Any discount");
+    let min_discounts = TARGET_PERCENTAGES.iter().map(|&min_discount| {
+        Callback::SetTarget {
+            product_id,
+            min_discount,
+        }
+        .button(format!("≥ {}% off", min_discount))
+    });
+    let custom_target = Callback::CustomTarget { product_id }.button("Custom…");
+    create_stop_track_keyboard(product_id).append_row(
+        std::iter::once(any_discount)
+            .chain(min_discounts)
+            .chain(std::iter::once(custom_target))
+            .collect::<Vec<_>>(),
+    )
+}
+
+/// Asks for the target of a tracked product, the answer is handled by
+/// `receive_target`.
+async fn start_custom_target(
+    bot: &Throttle<Bot>,
+    chat_id: ChatId,
+    dialogue: &BotDialogue,
+    product_id: i64,
+) -> CallbackResult {
+    dialogue.update(State::ReceiveTarget { product_id }).await?;
+
+    bot.send_message(
+        chat_id,
+        "Send the price you want to pay, e.g. 2.50, or the minimum discount, e.g. 25%. Send /cancel to stop.",
+    )
+    .await?;
+    Ok(Answer::Silent)
+}
+
+async fn receive_target_handler(
+    bot: Throttle<Bot>,
+    msg: Message,
+    dialogue: BotDialogue,
+    product_id: i64,
+) -> UpdateResult {
+    let chat_id = msg.chat.id;
+    receive_target(bot, msg, dialogue, product_id)
+        .await
+        .map_err(|e| e.in_update("receive target", Some(chat_id)))
+}
+
+async fn receive_target(
+    bot: Throttle<Bot>,
+    msg: Message,
+    dialogue: BotDialogue,
+    product_id: i64,
+) -> HandlerResult {
+    let Some(target) = msg.text().and_then(|text| text.parse::<Target>().ok()) else {
+        bot.send_message(
+            msg.chat.id,
+            "Send a price like 2.50 or a discount like 25%, or /cancel to stop",
+        )
+        .await?;
+        return Ok(());
+    };
+
+    if let Err(e) = dialogue
+        .update(State::ConfirmTarget { product_id, target })
+        .await
+    {
+        log::error!("Failed to update dialogue {}", e);
+        bot.send_message(msg.chat.id, "Something went wrong, try again later")
+            .await?;
+        return Ok(());
+    }
+
+    let keyboard = InlineKeyboardMarkup::default().append_row(vec![
+        Callback::ConfirmTarget { product_id }.button("Confirm"),
+        Callback::CancelDialogue.button("Cancel"),
+    ]);
+    bot.send_message(
+        msg.chat.id,
+        format!("Only get notified of {} for this product?", target),
+    )
+    .reply_markup(keyboard)
+    .await?;
+    Ok(())
+}
+
+async fn confirm_target(
+    bot: &Throttle<Bot>,
+    msg: &Message,
+    pool: &SqlitePool,
+    dialogue: &BotDialogue,
+    product_id: i64,
+) -> CallbackResult {
+    let chat_id = &msg.chat.id;
+    // the chat may have started another dialogue after this question was sent
+    let target = match dialogue.get().await {
+        Ok(Some(State::ConfirmTarget {
+            product_id: dialogue_product_id,
+            target,
+        })) if dialogue_product_id == product_id => target,
+        _ => {
+            bot.edit_message_text(*chat_id, msg.id, "This question has expired")
+                .await?;
+            return Ok(Answer::Silent);
+        }
+    };
+    log::info!(
+        "set target: product_id={} chat_id={} target={:?}",
+        product_id,
+        chat_id.0,
+        target
     );
-    InlineKeyboardMarkup::default().append_row(vec![button])
+    cancel_dialogue(dialogue).await;
+
+    let text = match db::set_tracking_target(pool, product_id, chat_id.0, &target).await {
+        Ok(0) => "You are not tracking this product".to_string(),
+        Ok(_) => format!("You will be notified of {}", target),
+        Err(e) => {
+            log::error!("Failed to set target {}", e);
+            "Failed to set target".to_string()
+        }
+    };
+    bot.edit_message_text(*chat_id, msg.id, text).await?;
+    Ok(Answer::Silent)
+}
+
+/// Ends the dialogue of a chat, returns `false` if there was nothing to cancel
+async fn cancel_dialogue(dialogue: &BotDialogue) -> bool {
+    let in_dialogue = matches!(dialogue.get().await, Ok(Some(state)) if state != State::Idle);
+    if let Err(e) = dialogue.exit().await {
+        log::error!("Failed to exit dialogue {}", e);
+    }
+    in_dialogue
 }
 
 async fn track_product(
     bot: &Throttle<Bot>,
-    msg: &Message,
+    chat_id: ChatId,
+    button_message: &ButtonMessage,
     pool: &SqlitePool,
     ah_client: &AHClient,
-    product_id: &str,
-) -> ResponseResult<()> {
-    let chat_id = &msg.chat.id;
+    product_id: i64,
+) -> CallbackResult {
+    let chat_id = &chat_id;
     log::info!(
         "start tracking: product_id={} chat_id={}",
         product_id,
         chat_id.0
     );
 
-    let product_response = ah_client.get_product(product_id).await?;
+    let product_response = ah_client.get_product(&product_id.to_string()).await?;
     let product = product_response
         .card
         .products
         .first()
-        .expect("No product found");
-
-    let insert = db::insert_product(&pool, product).await;
-    if insert.is_err() {
-        match insert.err().unwrap() {
-            sqlx::Error::Database(e) => {
-                if e.is_unique_violation() {
-                    log::info!("Product {} already exists in database", product.id);
-                } else {
-                    log::error!("Failed to insert product {}. Error: {}", product.id, e);
-                    bot.send_message(*chat_id, format!("Failed to track {}", product.title))
-                        .await?;
-                    return Ok(());
-                }
-            }
-            e => {
-                log::error!("Failed to insert product {}. Error: {}", product.id, e);
-                bot.send_message(*chat_id, format!("Failed to track {}", product.title))
-                    .await?;
-                return Ok(());
-            }
+        .ok_or(HandlerError::ProductNotFound)?;
+
+    let insert = db::insert_product(pool, product).await;
+    match insert {
+        Ok(_) => {}
+        Err(sqlx::Error::Database(e)) if e.is_unique_violation() => {
+            log::info!("Product {} already exists in database", product.id);
+        }
+        Err(e) => {
+            log::error!("Failed to insert product {}. Error: {}", product.id, e);
+            return Ok(Answer::Alert(format!("Failed to track {}", product.title)));
         }
     }
 
-    let insert_tracking = db::insert_product_tracking(&pool, product.id, chat_id.0).await;
-    if insert_tracking.is_err() {
-        match insert_tracking.err().unwrap() {
-            sqlx::Error::Database(e) => {
-                if e.is_unique_violation() {
-                    bot.send_message(*chat_id, "Already tracking this product")
-                        .await?;
-                }
-            }
-            _ => {
-                log::error!("Failed to insert product {}", product.id);
-                bot.send_message(*chat_id, format!("Failed to track {}", product.title))
-                    .await?;
-            }
+    let insert_tracking = db::insert_product_tracking(pool, product.id, chat_id.0).await;
+    match insert_tracking {
+        Ok(_) => {}
+        Err(sqlx::Error::Database(e)) if e.is_unique_violation() => {
+            return Ok(Answer::Toast(format!("Already tracking {}", product.title)));
+        }
+        Err(e) => {
+            log::error!(
+                "Failed to insert product tracking {}. Error: {}",
+                product.id,
+                e
+            );
+            return Ok(Answer::Alert(format!("Failed to track {}", product.title)));
         }
-        return Ok(());
     }
 
-    let keyboard = create_stop_track_keyboard(product.id);
-    bot.edit_message_reply_markup(*chat_id, msg.id)
-        .reply_markup(keyboard)
-        .await?;
+    let keyboard = match button_message {
+        ButtonMessage::Chat(..) => create_tracked_keyboard(product.id),
+        ButtonMessage::Inline(_) => create_inline_keyboard(product.id, true),
+    };
+    button_message.edit_keyboard(bot, keyboard).await?;
+    Ok(Answer::Toast(format!("Now tracking {}", product.title)))
+}
 
-    Ok(())
+async fn set_target(
+    bot: &Throttle<Bot>,
+    msg: &Message,
+    pool: &SqlitePool,
+    product_id: i64,
+    target: Target,
+) -> CallbackResult {
+    let chat_id = &msg.chat.id;
+    log::info!(
+        "set target: product_id={} chat_id={} target={:?}",
+        product_id,
+        chat_id.0,
+        target
+    );
+
+    let update = db::set_tracking_target(pool, product_id, chat_id.0, &target).await;
+    match update {
+        Ok(0) => Ok(Answer::Alert(
+            "You are not tracking this product".to_string(),
+        )),
+        Ok(_) => {
+            let keyboard = create_stop_track_keyboard(product_id);
+            bot.edit_message_reply_markup(*chat_id, msg.id)
+                .reply_markup(keyboard)
+                .await?;
+            Ok(Answer::Toast(format!("You will be notified of {}", target)))
+        }
+        Err(e) => {
+            log::error!("Failed to set target {}", e);
+            Ok(Answer::Alert("Failed to set target".to_string()))
+        }
+    }
 }
 
-async fn stop_tracking_product(
+/// Mutes, snoozes or unmutes a tracked product, see [`db::set_tracking_mute`],
+/// and updates the mute buttons of the message.
+async fn set_mute(
     bot: &Throttle<Bot>,
     msg: &Message,
     pool: &SqlitePool,
-    product_id: &str,
-) -> ResponseResult<()> {
+    product_id: i64,
+    send_notification: bool,
+    muted_until: Option<Date>,
+) -> CallbackResult {
     let chat_id = &msg.chat.id;
-    let parsed_product_id = product_id.parse::<i64>().expect("Invalid product id");
+    log::info!(
+        "set mute: product_id={} chat_id={} send_notification={} muted_until={:?}",
+        product_id,
+        chat_id.0,
+        send_notification,
+        muted_until
+    );
+
+    let updated =
+        db::set_tracking_mute(pool, product_id, chat_id.0, send_notification, muted_until).await?;
+    if updated == 0 {
+        return Ok(Answer::Alert(
+            "You are not tracking this product".to_string(),
+        ));
+    }
+
+    if let Some(keyboard) = msg.reply_markup() {
+        let muted = !send_notification || muted_until.is_some();
+        let mut rows = keyboard
+            .inline_keyboard
+            .iter()
+            .filter(|row| !row.iter().any(is_mute_button))
+            .cloned()
+            .collect::<Vec<_>>();
+        rows.push(mute_buttons(product_id, muted));
+        bot.edit_message_reply_markup(*chat_id, msg.id)
+            .reply_markup(InlineKeyboardMarkup::new(rows))
+            .await?;
+    }
+
+    let text = match (send_notification, muted_until) {
+        (false, _) => "Muted, you won't be notified of this product".to_string(),
+        (true, Some(until)) => format!("Snoozed until {}", until),
+        (true, None) => "Unmuted".to_string(),
+    };
+    Ok(Answer::Toast(text))
+}
+
+async fn stop_tracking_product(
+    bot: &Throttle<Bot>,
+    chat_id: ChatId,
+    button_message: &ButtonMessage,
+    pool: &SqlitePool,
+    product_id: i64,
+) -> CallbackResult {
+    let chat_id = &chat_id;
     log::info!(
         "stop tracking: product_id={} chat_id={}",
         product_id,
         chat_id.0
     );
 
-    let delete = db::delete_product_tracking(&pool, parsed_product_id, chat_id.0).await;
+    let delete = db::delete_product_tracking(pool, product_id, chat_id.0).await;
     match delete {
         Ok(_) => {
-            let keyboard = create_track_keyboard(parsed_product_id);
-            bot.edit_message_reply_markup(*chat_id, msg.id)
-                .reply_markup(keyboard)
-                .await?;
+            let keyboard = match button_message {
+                ButtonMessage::Chat(..) => create_track_keyboard(product_id),
+                ButtonMessage::Inline(_) => create_inline_keyboard(product_id, false),
+            };
+            button_message.edit_keyboard(bot, keyboard).await?;
+            Ok(Answer::Toast("Stopped tracking".to_string()))
         }
         Err(e) => {
             log::error!("Failed to delete product tracking {}", e);
-            bot.send_message(*chat_id, "Failed to stop tracking product")
-                .await?;
+            Ok(Answer::Alert("Failed to stop tracking product".to_string()))
         }
     }
-
-    Ok(())
 }
 
 async fn commands_handler(
@@ -256,84 +836,1192 @@ async fn commands_handler(
     cmd: Command,
     pool: SqlitePool,
     ah_client: AHClient,
-) -> ResponseResult<()> {
-    match cmd {
-        Command::Help | Command::Start => help_endpoint(bot, msg).await,
-        Command::Search(query) => search_endpoint(bot, msg, &pool, &ah_client, &query).await,
-        Command::List => list_endpoint(bot, msg, &pool).await,
-    }
+    config: Config,
+    dialogue: BotDialogue,
+) -> UpdateResult {
+    let chat_id = msg.chat.id;
+    handle_command(bot, msg, cmd, pool, ah_client, config, dialogue)
+        .await
+        .map_err(|e| e.in_update("command", Some(chat_id)))
+}
+
+async fn handle_command(
+    bot: Throttle<Bot>,
+    msg: Message,
+    cmd: Command,
+    pool: SqlitePool,
+    ah_client: AHClient,
+    config: Config,
+    dialogue: BotDialogue,
+) -> HandlerResult {
+    let chat_id = msg.chat.id;
+    let result = match cmd {
+        Command::Help | Command::Start => help_endpoint(bot.clone(), msg).await,
+        Command::Search(query) => {
+            search_endpoint(bot.clone(), msg, &pool, &ah_client, &config, &query).await
+        }
+        Command::List => list_endpoint(bot.clone(), msg, &pool, &config).await,
+        Command::Target(args) => target_endpoint(bot.clone(), msg, &pool, &args).await,
+        Command::Watch(query) => watch_endpoint(bot.clone(), msg, &pool, &query).await,
+        Command::Watches => watches_endpoint(bot.clone(), msg, &pool).await,
+        Command::History(product_id) => match product_id.trim().parse::<i64>() {
+            Ok(product_id) => send_price_history(&bot, msg.chat.id, &pool, product_id).await,
+            Err(_) => {
+                bot.send_message(
+                    msg.chat.id,
+                    "Usage: /history <product id>, e.g. /history 12345",
+                )
+                .await?;
+                Ok(())
+            }
+        },
+        Command::Predict => predict_endpoint(bot.clone(), msg, &pool).await,
+        Command::Shrinkflation => shrinkflation_endpoint(bot.clone(), msg, &pool).await,
+        Command::Cancel => {
+            let text = if cancel_dialogue(&dialogue).await {
+                "Cancelled"
+            } else {
+                "Nothing to cancel"
+            };
+            bot.send_message(msg.chat.id, text).await?;
+            Ok(())
+        }
+        Command::Settings => settings_endpoint(bot.clone(), msg, &pool).await,
+    };
+    reply_error(&bot, chat_id, result).await
 }
 
-async fn help_endpoint(bot: Throttle<Bot>, msg: Message) -> ResponseResult<()> {
+async fn help_endpoint(bot: Throttle<Bot>, msg: Message) -> HandlerResult {
     bot.send_message(msg.chat.id, Command::descriptions().to_string())
         .await?;
     Ok(())
 }
 
-async fn list_endpoint(bot: Throttle<Bot>, msg: Message, pool: &SqlitePool) -> ResponseResult<()> {
-    let tracked_products = db::get_all_tracked_products(pool, msg.chat.id.0).await;
-    match tracked_products {
-        Ok(products) => {
-            if products.len() == 0 {
-                bot.send_message(msg.chat.id, "No tracked products").await?;
-                return Ok(());
-            }
+/// Returns the text and keyboard of a page of tracked products. Every product has
+/// a button to show its product card, followed by rows to sort, navigate the
+/// pages and act on all products at once.
+fn create_list_page(
+    mut products: Vec<db::TrackedProduct>,
+    sort: ListSort,
+    page: usize,
+    page_size: usize,
+) -> (String, InlineKeyboardMarkup) {
+    if products.is_empty() {
+        return (
+            "No tracked products".to_string(),
+            InlineKeyboardMarkup::default(),
+        );
+    }
+
+    match sort {
+        ListSort::Name => products.sort_by_key(|product| product.name.to_lowercase()),
+        ListSort::Price => products.sort_by_key(|product| (product.price.is_none(), product.price)),
+        ListSort::OnBonus => {
+            products.sort_by_key(|product| (!product.on_bonus, product.name.to_lowercase()))
+        }
+    }
 
-            for product in products {
-                let keyboard = create_stop_track_keyboard(product.id);
+    // products may have been untracked since the page was sent
+    let page_size = page_size.max(1);
+    let total_pages = products.len().div_ceil(page_size);
+    let page = page.min(total_pages - 1);
+    let first_position = page * page_size + 1;
+    let all_muted = products.iter().all(|product| !product.send_notification);
+    let total = products.len();
 
-                let image_url = url::Url::parse(&product.image_url).unwrap();
-                bot.send_photo(msg.chat.id, InputFile::url(image_url))
-                    .caption(product.name)
-                    .reply_markup(keyboard)
-                    .disable_notification(true)
-                    .await?;
+    let page_products = products
+        .into_iter()
+        .skip(page * page_size)
+        .take(page_size)
+        .collect::<Vec<_>>();
+    let sort_name = match sort {
+        ListSort::Name => "name",
+        ListSort::Price => "price",
+        ListSort::OnBonus => "bonus",
+    };
+    let mut lines = vec![format!(
+        "Tracked products ({}–{} of {}), by {}:",
+        first_position,
+        first_position + page_products.len() - 1,
+        total,
+        sort_name
+    )];
+    let mut keyboard = InlineKeyboardMarkup::default();
+    for (position, product) in (first_position..).zip(page_products) {
+        let price = product
+            .price
+            .map(format_price)
+            .unwrap_or_else(|| "no price yet".to_string());
+        let muted = match (product.send_notification, product.muted_until) {
+            (false, _) => " 🔕".to_string(),
+            (true, Some(until)) => format!(" 💤 until {}", until),
+            (true, None) => String::new(),
+        };
+        lines.push(format!(
+            "{}. {} - {}{}",
+            position, product.name, price, muted
+        ));
+        if let Some(status) = tracked_product_status(&product) {
+            lines.push(format!("    {}", status));
+        }
+
+        let title = product
+            .name
+            .chars()
+            .take(BUTTON_TITLE_LENGTH)
+            .collect::<String>();
+        keyboard = keyboard.append_row(vec![Callback::ShowProduct {
+            product_id: product.id,
+        }
+        .button(format!("{}. {}", position, title))]);
+    }
+
+    let sorting = ListSort::ALL
+        .into_iter()
+        .map(|option| {
+            let text = match option {
+                ListSort::Name => "Name",
+                ListSort::Price => "Price",
+                ListSort::OnBonus => "On bonus",
+            };
+            let text = if option == sort {
+                format!("✓ {}", text)
+            } else {
+                text.to_string()
+            };
+            Callback::ListPage {
+                sort: option,
+                page: 0,
+            }
+            .button(text)
+        })
+        .collect::<Vec<_>>();
+    keyboard = keyboard.append_row(sorting);
+
+    let mut navigation = vec![];
+    if page > 0 {
+        navigation.push(
+            Callback::ListPage {
+                sort,
+                page: page - 1,
+            }
+            .button("◀ Prev"),
+        );
+    }
+    if page + 1 < total_pages {
+        navigation.push(
+            Callback::ListPage {
+                sort,
+                page: page + 1,
             }
+            .button("Next ▶"),
+        );
+    }
+    if !navigation.is_empty() {
+        keyboard = keyboard.append_row(navigation);
+    }
+
+    let mute = if all_muted {
+        Callback::MuteAll {
+            mute: false,
+            sort,
+            page,
+        }
+        .button("🔔 Unmute all")
+    } else {
+        Callback::MuteAll {
+            mute: true,
+            sort,
+            page,
+        }
+        .button("🔕 Mute all")
+    };
+    keyboard = keyboard.append_row(vec![
+        mute,
+        Callback::UntrackAll { sort, page }.button("Untrack all"),
+    ]);
+
+    (lines.join("\n"), keyboard)
+}
+
+/// Returns the discount of a tracked product and when its price was last
+/// fetched, e.g. `🏷 1 + 1 gratis until 2026-10-25 · checked 2026-10-19 10:00 UTC`.
+fn tracked_product_status(product: &db::TrackedProduct) -> Option<String> {
+    let discount = match (
+        &product.discount,
+        product.discount_start_date,
+        product.discount_end_date,
+    ) {
+        (Some(discount), _, Some(end)) if product.on_bonus => {
+            Some(format!("🏷 {} until {}", discount, end))
         }
-        Err(_) => {
+        (Some(discount), Some(start), _) => Some(format!("🏷 {} from {}", discount, start)),
+        _ => None,
+    };
+    let checked = product.checked_at.map(|checked_at| {
+        format!(
+            "checked {} {:02}:{:02} UTC",
+            checked_at.date(),
+            checked_at.hour(),
+            checked_at.minute()
+        )
+    });
+
+    let status = discount.into_iter().chain(checked).collect::<Vec<_>>();
+    (!status.is_empty()).then(|| status.join(" · "))
+}
+
+async fn list_endpoint(
+    bot: Throttle<Bot>,
+    msg: Message,
+    pool: &SqlitePool,
+    config: &Config,
+) -> HandlerResult {
+    let tracked_products = match db::get_all_tracked_products(pool, msg.chat.id.0).await {
+        Ok(products) => products,
+        Err(e) => {
+            log::error!("Failed to retrieve tracked products {}", e);
             bot.send_message(
                 msg.chat.id,
                 "Failed to retrieve list of tracked products, try again later",
             )
             .await?;
+            return Ok(());
+        }
+    };
+
+    let (text, keyboard) =
+        create_list_page(tracked_products, ListSort::Name, 0, config.list_page_size);
+    bot.send_message(msg.chat.id, text)
+        .reply_markup(keyboard)
+        .disable_notification(true)
+        .await?;
+    Ok(())
+}
+
+/// Replaces the message with a page of tracked products
+async fn list_page(
+    bot: &Throttle<Bot>,
+    msg: &Message,
+    pool: &SqlitePool,
+    config: &Config,
+    sort: ListSort,
+    page: usize,
+) -> CallbackResult {
+    let chat_id = &msg.chat.id;
+    log::info!(
+        "list page: sort={:?} page={} chat_id={}",
+        sort,
+        page,
+        chat_id.0
+    );
+
+    let tracked_products = db::get_all_tracked_products(pool, chat_id.0).await?;
+    let (text, keyboard) = create_list_page(tracked_products, sort, page, config.list_page_size);
+    let edit = bot
+        .edit_message_text(*chat_id, msg.id, text)
+        .reply_markup(keyboard)
+        .await;
+    match edit {
+        // pressing the current sort on the first page doesn't change anything
+        Ok(_) | Err(RequestError::Api(ApiError::MessageNotModified)) => Ok(Answer::Silent),
+        Err(e) => Err(e.into()),
+    }
+}
+
+/// Asks to confirm stopping tracking all products
+async fn untrack_all(
+    bot: &Throttle<Bot>,
+    msg: &Message,
+    pool: &SqlitePool,
+    sort: ListSort,
+    page: usize,
+) -> CallbackResult {
+    let tracked_products = db::get_all_tracked_products_ids(pool, msg.chat.id.0).await?;
+    if tracked_products.is_empty() {
+        return Ok(Answer::Alert("No tracked products".to_string()));
+    }
+
+    let keyboard = InlineKeyboardMarkup::new(vec![vec![
+        Callback::ConfirmUntrackAll.button("Untrack all"),
+        Callback::ListPage { sort, page }.button("Cancel"),
+    ]]);
+    bot.edit_message_text(
+        msg.chat.id,
+        msg.id,
+        format!(
+            "Stop tracking all {} products? This can't be undone.",
+            tracked_products.len()
+        ),
+    )
+    .reply_markup(keyboard)
+    .await?;
+    Ok(Answer::Silent)
+}
+
+async fn confirm_untrack_all(
+    bot: &Throttle<Bot>,
+    msg: &Message,
+    pool: &SqlitePool,
+) -> CallbackResult {
+    let chat_id = &msg.chat.id;
+    log::info!("stop tracking all: chat_id={}", chat_id.0);
+
+    let deleted = db::delete_all_product_trackings(pool, chat_id.0).await?;
+    bot.edit_message_text(
+        *chat_id,
+        msg.id,
+        format!("Stopped tracking {} products", deleted),
+    )
+    .await?;
+    Ok(Answer::Silent)
+}
+
+async fn mute_all(
+    bot: &Throttle<Bot>,
+    msg: &Message,
+    pool: &SqlitePool,
+    config: &Config,
+    mute: bool,
+    sort: ListSort,
+    page: usize,
+) -> CallbackResult {
+    log::info!("mute all: mute={} chat_id={}", mute, msg.chat.id.0);
+
+    db::set_tracking_notifications(pool, msg.chat.id.0, !mute).await?;
+    list_page(bot, msg, pool, config, sort, page).await?;
+    let text = if mute {
+        "Muted all tracked products"
+    } else {
+        "Unmuted all tracked products"
+    };
+    Ok(Answer::Toast(text.to_string()))
+}
+
+/// Returns the text and keyboard of the notification settings of a chat
+fn create_settings_message(settings: &ChatSettings) -> (String, InlineKeyboardMarkup) {
+    let on_off = |on: bool| if on { "on" } else { "off" };
+    let text = format!(
+        "Notification settings (Dutch time):\nDays: {}\nTime: {:02}:00\n\"Nothing on sale\" message: {}\nLast day reminder: {}\nDigest: {}",
+        settings.days_text(),
+        settings.notification_hour,
+        on_off(settings.send_nothing_on_sale),
+        on_off(settings.last_day_reminder),
+        on_off(settings.digest)
+    );
+
+    let days = WEEKDAYS
+        .into_iter()
+        .map(|day| {
+            // two letters, so all days fit on one row
+            let name = &weekday_abbreviation(day)[..2];
+            let text = if settings.has_day(day) {
+                format!("✓{}", name)
+            } else {
+                name.to_string()
+            };
+            Callback::ToggleDay { day }.button(text)
+        })
+        .collect::<Vec<_>>();
+    let hour = settings.notification_hour;
+    let time = vec![
+        Callback::SetNotificationHour {
+            hour: (hour + 23) % 24,
+        }
+        .button("−1 hour"),
+        Callback::SetNotificationHour {
+            hour: (hour + 1) % 24,
+        }
+        .button("+1 hour"),
+    ];
+    let nothing_on_sale = if settings.send_nothing_on_sale {
+        "Turn off \"nothing on sale\""
+    } else {
+        "Turn on \"nothing on sale\""
+    };
+
+    let last_day_reminder = if settings.last_day_reminder {
+        "Turn off last day reminder"
+    } else {
+        "Turn on last day reminder"
+    };
+
+    let digest = if settings.digest {
+        "Get a message per discount"
+    } else {
+        "Get all discounts in one message"
+    };
+
+    let keyboard = InlineKeyboardMarkup::new(vec![
+        days,
+        time,
+        vec![Callback::ToggleNothingOnSale.button(nothing_on_sale)],
+        vec![Callback::ToggleLastDayReminder.button(last_day_reminder)],
+        vec![Callback::ToggleDigest.button(digest)],
+    ]);
+    (text, keyboard)
+}
+
+async fn settings_endpoint(bot: Throttle<Bot>, msg: Message, pool: &SqlitePool) -> HandlerResult {
+    let settings = db::get_chat_settings(pool, msg.chat.id.0)
+        .await?
+        .unwrap_or_else(|| ChatSettings::new(msg.chat.id.0));
+
+    let (text, keyboard) = create_settings_message(&settings);
+    bot.send_message(msg.chat.id, text)
+        .reply_markup(keyboard)
+        .await?;
+    Ok(())
+}
+
+/// Changes the notification settings of a chat and shows the new settings
+async fn update_settings(
+    bot: &Throttle<Bot>,
+    msg: &Message,
+    pool: &SqlitePool,
+    change: impl FnOnce(&mut ChatSettings),
+) -> CallbackResult {
+    let chat_id = &msg.chat.id;
+    let mut settings = db::get_chat_settings(pool, chat_id.0)
+        .await?
+        .unwrap_or_else(|| ChatSettings::new(chat_id.0));
+    change(&mut settings);
+    log::info!("update settings: {:?}", settings);
+
+    db::set_chat_settings(pool, &settings).await?;
+    let (text, keyboard) = create_settings_message(&settings);
+    bot.edit_message_text(*chat_id, msg.id, text)
+        .reply_markup(keyboard)
+        .await?;
+    Ok(Answer::Silent)
+}
+
+async fn target_endpoint(
+    bot: Throttle<Bot>,
+    msg: Message,
+    pool: &SqlitePool,
+    args: &str,
+) -> HandlerResult {
+    let parsed = args.split_once(' ').and_then(|(product_id, target)| {
+        Some((
+            product_id.trim().parse::<i64>().ok()?,
+            target.parse::<Target>().ok()?,
+        ))
+    });
+    let Some((product_id, target)) = parsed else {
+        bot.send_message(
+            msg.chat.id,
+            "Usage: /target <product id> <price, percentage or off>, e.g. /target 12345 25%",
+        )
+        .await?;
+        return Ok(());
+    };
+
+    let update = db::set_tracking_target(pool, product_id, msg.chat.id.0, &target).await;
+    match update {
+        Ok(0) => {
+            bot.send_message(msg.chat.id, "You are not tracking this product")
+                .await?;
+        }
+        Ok(_) => {
+            bot.send_message(msg.chat.id, format!("You will be notified of {}", target))
+                .await?;
+        }
+        Err(e) => {
+            log::error!("Failed to set target {}", e);
+            bot.send_message(msg.chat.id, "Failed to set target")
+                .await?;
         }
     }
     Ok(())
 }
 
+async fn watch_endpoint(
+    bot: Throttle<Bot>,
+    msg: Message,
+    pool: &SqlitePool,
+    query: &str,
+) -> HandlerResult {
+    let query = query.trim();
+    if query.is_empty() {
+        bot.send_message(
+            msg.chat.id,
+            "Usage: /watch <search query>, e.g. /watch hagelslag",
+        )
+        .await?;
+        return Ok(());
+    }
+    log::info!("watch: query={} chat_id={}", query, msg.chat.id.0);
+
+    let insert = db::insert_watch(pool, msg.chat.id.0, query).await;
+    match insert {
+        Ok(_) => {
+            bot.send_message(
+                msg.chat.id,
+                format!(
+                    "You will be notified when products matching \"{}\" go on bonus",
+                    query
+                ),
+            )
+            .await?;
+        }
+        Err(sqlx::Error::Database(e)) if e.is_unique_violation() => {
+            bot.send_message(msg.chat.id, "Already watching this search")
+                .await?;
+        }
+        Err(e) => {
+            log::error!("Failed to insert watch {}", e);
+            bot.send_message(msg.chat.id, "Failed to watch this search")
+                .await?;
+        }
+    }
+    Ok(())
+}
+
+/// All the watches of a chat: saved searches, followed brands and categories
+struct ChatWatches {
+    queries: Vec<db::Watch>,
+    brands: Vec<db::BrandWatch>,
+    taxonomies: Vec<db::TaxonomyWatch>,
+}
+
+impl ChatWatches {
+    async fn fetch(pool: &SqlitePool, chat_id: i64) -> Result<Self, sqlx::Error> {
+        Ok(Self {
+            queries: db::get_watches(pool, chat_id).await?,
+            brands: db::get_brand_watches(pool, chat_id).await?,
+            taxonomies: db::get_taxonomy_watches(pool, chat_id).await?,
+        })
+    }
+
+    fn is_empty(&self) -> bool {
+        self.queries.is_empty() && self.brands.is_empty() && self.taxonomies.is_empty()
+    }
+
+    fn keyboard(&self) -> InlineKeyboardMarkup {
+        let queries = self.queries.iter().map(|watch| {
+            (
+                format!("Remove \"{}\"", watch.query),
+                WatchKind::Query,
+                watch.id,
+            )
+        });
+        let brands = self.brands.iter().map(|watch| {
+            (
+                format!("Unfollow brand {}", watch.brand),
+                WatchKind::Brand,
+                watch.id,
+            )
+        });
+        let taxonomies = self.taxonomies.iter().map(|watch| {
+            (
+                format!("Unfollow category {}", watch.taxonomy_name),
+                WatchKind::Taxonomy,
+                watch.id,
+            )
+        });
+        let rows = queries
+            .chain(brands)
+            .chain(taxonomies)
+            .map(|(text, kind, watch_id)| {
+                vec![Callback::RemoveWatch { kind, watch_id }.button(text)]
+            });
+        InlineKeyboardMarkup::new(rows)
+    }
+}
+
+async fn watches_endpoint(bot: Throttle<Bot>, msg: Message, pool: &SqlitePool) -> HandlerResult {
+    let watches = ChatWatches::fetch(pool, msg.chat.id.0).await;
+    match watches {
+        Ok(watches) => {
+            if watches.is_empty() {
+                bot.send_message(msg.chat.id, "No watches").await?;
+                return Ok(());
+            }
+
+            bot.send_message(msg.chat.id, "Your watches:")
+                .reply_markup(watches.keyboard())
+                .await?;
+        }
+        Err(e) => {
+            log::error!("Failed to retrieve watches {}", e);
+            bot.send_message(
+                msg.chat.id,
+                "Failed to retrieve list of watches, try again later",
+            )
+            .await?;
+        }
+    }
+    Ok(())
+}
+
+async fn remove_watch(
+    bot: &Throttle<Bot>,
+    msg: &Message,
+    pool: &SqlitePool,
+    kind: WatchKind,
+    watch_id: i64,
+) -> CallbackResult {
+    let chat_id = &msg.chat.id;
+    log::info!("remove watch: watch_id={} chat_id={}", watch_id, chat_id.0);
+
+    let delete = match kind {
+        WatchKind::Brand => db::delete_brand_watch(pool, watch_id, chat_id.0).await,
+        WatchKind::Taxonomy => db::delete_taxonomy_watch(pool, watch_id, chat_id.0).await,
+        WatchKind::Query => db::delete_watch(pool, watch_id, chat_id.0).await,
+    };
+    if let Err(e) = delete {
+        log::error!("Failed to delete watch {}", e);
+        return Ok(Answer::Alert("Failed to remove watch".to_string()));
+    }
+
+    match ChatWatches::fetch(pool, chat_id.0).await {
+        Ok(watches) if !watches.is_empty() => {
+            bot.edit_message_reply_markup(*chat_id, msg.id)
+                .reply_markup(watches.keyboard())
+                .await?;
+        }
+        _ => {
+            bot.edit_message_text(*chat_id, msg.id, "No watches")
+                .await?;
+        }
+    }
+    Ok(Answer::Toast("Removed".to_string()))
+}
+
+/// Follows the brand or the category of a product, depending on `kind`
+async fn follow(
+    msg: &Message,
+    pool: &SqlitePool,
+    ah_client: &AHClient,
+    kind: WatchKind,
+    product_id: i64,
+) -> CallbackResult {
+    let chat_id = &msg.chat.id;
+    log::info!("follow: product_id={} chat_id={}", product_id, chat_id.0);
+
+    let product_response = ah_client.get_product(&product_id.to_string()).await?;
+    let product = product_response
+        .card
+        .products
+        .first()
+        .ok_or(HandlerError::ProductNotFound)?;
+
+    let (insert, followed) = match kind {
+        WatchKind::Brand => {
+            if product.brand.is_empty() {
+                return Ok(Answer::Alert("This product has no brand".to_string()));
+            }
+            let insert = db::insert_brand_watch(pool, chat_id.0, &product.brand).await;
+            (insert, format!("brand {}", product.brand))
+        }
+        _ => {
+            let Some(taxonomy) = product.get_taxonomy() else {
+                return Ok(Answer::Alert("This product has no category".to_string()));
+            };
+            let insert =
+                db::insert_taxonomy_watch(pool, chat_id.0, taxonomy.id, &taxonomy.name).await;
+            (insert, format!("category {}", taxonomy.name))
+        }
+    };
+
+    match insert {
+        Ok(_) => Ok(Answer::Toast(format!(
+            "You will be notified when products of the {} go on bonus",
+            followed
+        ))),
+        Err(sqlx::Error::Database(e)) if e.is_unique_violation() => {
+            Ok(Answer::Toast(format!("Already following the {}", followed)))
+        }
+        Err(e) => {
+            log::error!("Failed to follow {}: {}", followed, e);
+            Ok(Answer::Alert(format!("Failed to follow the {}", followed)))
+        }
+    }
+}
+
+async fn send_price_history(
+    bot: &Throttle<Bot>,
+    chat_id: ChatId,
+    pool: &SqlitePool,
+    product_id: i64,
+) -> HandlerResult {
+    log::info!("history: product_id={} chat_id={}", product_id, chat_id.0);
+
+    let name = db::get_product_name(pool, product_id).await;
+    let history = db::get_price_history(pool, product_id).await;
+    let (name, history) = match (name, history) {
+        (Ok(Some(name)), Ok(history)) if !history.is_empty() => (name, history),
+        (Ok(_), Ok(_)) => {
+            bot.send_message(
+                chat_id,
+                "No price history for this product yet, track it to start recording prices",
+            )
+            .await?;
+            return Ok(());
+        }
+        (Err(e), _) | (_, Err(e)) => {
+            log::error!("Failed to retrieve price history {}", e);
+            bot.send_message(chat_id, "Failed to retrieve price history, try again later")
+                .await?;
+            return Ok(());
+        }
+    };
+
+    // drawing and encoding the chart is CPU bound, keep it off the runtime
+    let title = name.clone();
+    let png = tokio::task::spawn_blocking(move || chart::render_price_history(&title, &history))
+        .await
+        .map_err(|e| chart::ChartError::Drawing(e.to_string()))??;
+    bot.send_photo(chat_id, InputFile::memory(png).file_name("history.png"))
+        .caption(name)
+        .disable_notification(true)
+        .await?;
+    Ok(())
+}
+
+/// Returns the bonus predictions of a tracked product as a single line, or `None`
+/// if no price was recorded for it yet.
+async fn predict_product(
+    pool: &SqlitePool,
+    product: &db::TrackedProduct,
+    today: Date,
+) -> Result<Option<String>, sqlx::Error> {
+    let Some(first_recorded) = db::get_first_recorded_date(pool, product.id).await? else {
+        return Ok(None);
+    };
+    let periods = db::get_bonus_periods(pool, product.id).await?;
+
+    let weeks = prediction::predict_bonus_weeks(
+        &periods,
+        first_recorded,
+        today,
+        prediction::PREDICTED_WEEKS,
+    )
+    .iter()
+    .map(|week| {
+        format!(
+            "{} {} {:.0}%",
+            week.week_start.day(),
+            &week.week_start.month().to_string()[..3],
+            week.probability * 100.0
+        )
+    })
+    .collect::<Vec<_>>()
+    .join(" · ");
+    Ok(Some(format!("{}\n{}", product.name, weeks)))
+}
+
+async fn predict_endpoint(bot: Throttle<Bot>, msg: Message, pool: &SqlitePool) -> HandlerResult {
+    let tracked_products = match db::get_all_tracked_products(pool, msg.chat.id.0).await {
+        Ok(products) => products,
+        Err(e) => {
+            log::error!("Failed to retrieve tracked products {}", e);
+            bot.send_message(
+                msg.chat.id,
+                "Failed to retrieve list of tracked products, try again later",
+            )
+            .await?;
+            return Ok(());
+        }
+    };
+    if tracked_products.is_empty() {
+        bot.send_message(msg.chat.id, "No tracked products").await?;
+        return Ok(());
+    }
+
+    let today = time::OffsetDateTime::now_utc().date();
+    let mut predictions = vec!["Chance of being on bonus in the week starting:".to_string()];
+    for product in &tracked_products {
+        match predict_product(pool, product, today).await {
+            Ok(Some(prediction)) => predictions.push(prediction),
+            Ok(None) => predictions.push(format!("{}\nNo prices recorded yet", product.name)),
+            Err(e) => log::error!("Failed to predict bonus of {}: {}", product.id, e),
+        }
+    }
+
+    for message in split_message(predictions, "\n\n") {
+        bot.send_message(msg.chat.id, message).await?;
+    }
+    Ok(())
+}
+
+/// Maximum number of products listed by the `/shrinkflation` report, so the message
+/// stays below Telegram's length limit.
+const SHRINKFLATION_REPORT_LIMIT: usize = 20;
+
+async fn shrinkflation_endpoint(
+    bot: Throttle<Bot>,
+    msg: Message,
+    pool: &SqlitePool,
+) -> HandlerResult {
+    let shrunk_products = db::get_shrinkflation(pool, PrimitiveDateTime::MIN).await;
+    match shrunk_products {
+        Ok(products) => {
+            if products.is_empty() {
+                bot.send_message(msg.chat.id, "No shrinkflation detected so far")
+                    .await?;
+                return Ok(());
+            }
+
+            let mut report = products
+                .iter()
+                .take(SHRINKFLATION_REPORT_LIMIT)
+                .map(|product| {
+                    format!(
+                        "{} \\({}\\)",
+                        product.message(),
+                        escape(product.detected_at.date().to_string().as_str())
+                    )
+                })
+                .collect::<Vec<_>>()
+                .join("\n\n");
+            if products.len() > SHRINKFLATION_REPORT_LIMIT {
+                report.push_str(&escape(&format!(
+                    "\n\nShowing the latest {} of {} products",
+                    SHRINKFLATION_REPORT_LIMIT,
+                    products.len()
+                )));
+            }
+            bot.send_message(msg.chat.id, report)
+                .parse_mode(ParseMode::MarkdownV2)
+                .await?;
+        }
+        Err(e) => {
+            log::error!("Failed to retrieve shrinkflation report {}", e);
+            bot.send_message(
+                msg.chat.id,
+                "Failed to retrieve shrinkflation report, try again later",
+            )
+            .await?;
+        }
+    }
+    Ok(())
+}
+
+/// Maximum number of characters of a product title shown on a search result button
+const BUTTON_TITLE_LENGTH: usize = 40;
+
+/// Returns the text and keyboard of a page of search results. Every result has a
+/// button to show its product card, and the last row allows to navigate the pages.
+fn create_search_page(
+    query: &str,
+    search_id: i64,
+    results: &SearchResults,
+    tracked_products: &HashSet<i64>,
+) -> (String, InlineKeyboardMarkup) {
+    let products = results
+        .cards
+        .iter()
+        .filter_map(|card| card.products.first())
+        .collect::<Vec<_>>();
+    if products.is_empty() {
+        return (
+            format!("No results for \"{}\"", query),
+            InlineKeyboardMarkup::default(),
+        );
+    }
+
+    let page = &results.page;
+    let first_position = page.number * page.size + 1;
+    let mut lines = vec![format!(
+        "Results for \"{}\" ({}–{} of {}):",
+        query,
+        first_position,
+        first_position + products.len() as i64 - 1,
+        page.total_elements
+    )];
+    let mut keyboard = InlineKeyboardMarkup::default();
+    for (position, product) in (first_position..).zip(products) {
+        let tracked = if tracked_products.contains(&product.id) {
+            " ✓"
+        } else {
+            ""
+        };
+        lines.push(format!(
+            "{}. {} - €{} {}{}",
+            position, product.title, product.price.now, product.price.unit_size, tracked
+        ));
+
+        let title = product
+            .title
+            .chars()
+            .take(BUTTON_TITLE_LENGTH)
+            .collect::<String>();
+        keyboard = keyboard.append_row(vec![Callback::ShowProduct {
+            product_id: product.id,
+        }
+        .button(format!("{}. {}", position, title))]);
+    }
+
+    let mut navigation = vec![];
+    if page.number > 0 {
+        navigation.push(
+            Callback::SearchPage {
+                search_id,
+                page: (page.number - 1) as usize,
+            }
+            .button("◀ Prev"),
+        );
+    }
+    if page.number + 1 < page.total_pages {
+        navigation.push(
+            Callback::SearchPage {
+                search_id,
+                page: (page.number + 1) as usize,
+            }
+            .button("Next ▶"),
+        );
+    }
+    if !navigation.is_empty() {
+        keyboard = keyboard.append_row(navigation);
+    }
+
+    (lines.join("\n"), keyboard)
+}
+
 async fn search_endpoint(
     bot: Throttle<Bot>,
     msg: Message,
     pool: &SqlitePool,
     ah_client: &AHClient,
+    config: &Config,
     query: &String,
-) -> ResponseResult<()> {
+) -> HandlerResult {
     log::info!("search: query={}", query);
-    let search_results = ah_client.search_products(query, 3).await?;
+    let search_results = ah_client
+        .search_products(query, config.search_page_size, 0)
+        .await?;
+
+    let search_id = match db::insert_search(pool, msg.chat.id.0, query).await {
+        Ok(search_id) => search_id,
+        Err(e) => {
+            log::error!("Failed to insert search {}", e);
+            bot.send_message(msg.chat.id, "Search failed, try again later")
+                .await?;
+            return Ok(());
+        }
+    };
 
     let tracked_products = db::get_all_tracked_products_ids(pool, msg.chat.id.0)
         .await
         .unwrap_or_default();
     let tracked_products_set = tracked_products.into_iter().collect::<HashSet<_>>();
 
-    for card in search_results.cards {
-        let product = card.products.first().unwrap();
+    let (text, keyboard) =
+        create_search_page(query, search_id, &search_results, &tracked_products_set);
+    bot.send_message(msg.chat.id, text)
+        .reply_markup(keyboard)
+        .disable_notification(true)
+        .await?;
 
-        let keyboard = if tracked_products_set.contains(&product.id) {
-            create_stop_track_keyboard(product.id)
-        } else {
-            create_track_keyboard(product.id)
-        };
+    Ok(())
+}
+
+/// Replaces a page of search results with another page
+async fn search_page(
+    bot: &Throttle<Bot>,
+    msg: &Message,
+    pool: &SqlitePool,
+    ah_client: &AHClient,
+    config: &Config,
+    search_id: i64,
+    page: usize,
+) -> CallbackResult {
+    let chat_id = &msg.chat.id;
+    log::info!(
+        "search page: search_id={} page={} chat_id={}",
+        search_id,
+        page,
+        chat_id.0
+    );
+
+    let query = match db::get_search_query(pool, search_id, chat_id.0).await {
+        Ok(Some(query)) => query,
+        Ok(None) => {
+            return Ok(Answer::Alert(
+                "This search is no longer available, search again".to_string(),
+            ));
+        }
+        Err(e) => {
+            log::error!("Failed to retrieve search {}", e);
+            return Ok(Answer::Alert("Search failed, try again later".to_string()));
+        }
+    };
+    let search_results = ah_client
+        .search_products(&query, config.search_page_size, page)
+        .await?;
+
+    let tracked_products = db::get_all_tracked_products_ids(pool, chat_id.0)
+        .await
+        .unwrap_or_default();
+    let tracked_products_set = tracked_products.into_iter().collect::<HashSet<_>>();
+
+    let (text, keyboard) =
+        create_search_page(&query, search_id, &search_results, &tracked_products_set);
+    bot.edit_message_text(*chat_id, msg.id, text)
+        .reply_markup(keyboard)
+        .await?;
+
+    Ok(Answer::Silent)
+}
 
-        let image_url = product.images.last().unwrap().url.clone();
-        bot.send_photo(msg.chat.id, InputFile::url(image_url))
-            .caption(format!(
-                "{} - €{} {}",
-                product.title, product.price.now, product.price.unit_size
-            ))
-            .reply_markup(keyboard)
-            .disable_notification(true)
+/// Finds the webshop id of the first AH product link in a message, e.g. `12345`
+/// in `https://www.ah.nl/producten/product/wi12345/ah-hagelslag-puur`.
+fn find_webshop_id(text: &str) -> Option<String> {
+    text.split_whitespace()
+        .filter_map(|word| url::Url::parse(word).ok())
+        .filter(|url| {
+            url.host_str()
+                .is_some_and(|host| host == "ah.nl" || host.ends_with(".ah.nl"))
+        })
+        .find_map(|url| {
+            let mut segments = url.path_segments()?;
+            segments.find(|segment| *segment == "product")?;
+            let webshop_id = segments.next()?.strip_prefix("wi")?;
+            if !webshop_id.is_empty() && webshop_id.chars().all(|c| c.is_ascii_digit()) {
+                Some(webshop_id.to_string())
+            } else {
+                None
+            }
+        })
+}
+
+/// Replies to a message containing an AH product link with the product card, so
+/// products can be tracked without searching for them.
+async fn product_link_handler(
+    bot: Throttle<Bot>,
+    msg: Message,
+    webshop_id: String,
+    pool: SqlitePool,
+    ah_client: AHClient,
+) -> UpdateResult {
+    let chat_id = msg.chat.id;
+    let result = send_linked_product(bot.clone(), msg, webshop_id, pool, ah_client).await;
+    reply_error(&bot, chat_id, result)
+        .await
+        .map_err(|e| e.in_update("product link", Some(chat_id)))
+}
+
+async fn send_linked_product(
+    bot: Throttle<Bot>,
+    msg: Message,
+    webshop_id: String,
+    pool: SqlitePool,
+    ah_client: AHClient,
+) -> HandlerResult {
+    log::info!("product link: webshop_id={}", webshop_id);
+    match ah_client.find_product(&webshop_id).await? {
+        Some(product) => send_product_card(&bot, msg.chat.id, &pool, &product).await,
+        None => {
+            bot.send_message(msg.chat.id, "Product not found").await?;
+            Ok(())
+        }
+    }
+}
+
+async fn barcode_handler(
+    bot: Throttle<Bot>,
+    msg: Message,
+    photo: PhotoSize,
+    pool: SqlitePool,
+    ah_client: AHClient,
+) -> UpdateResult {
+    let chat_id = msg.chat.id;
+    let result = send_barcode_product(bot.clone(), msg, photo, pool, ah_client).await;
+    reply_error(&bot, chat_id, result)
+        .await
+        .map_err(|e| e.in_update("barcode", Some(chat_id)))
+}
+
+/// Replies to a photo of a barcode with the card of the product it belongs to.
+async fn send_barcode_product(
+    bot: Throttle<Bot>,
+    msg: Message,
+    photo: PhotoSize,
+    pool: SqlitePool,
+    ah_client: AHClient,
+) -> HandlerResult {
+    let file = bot.get_file(photo.file.id).await?;
+    let mut photo_bytes = Vec::with_capacity(file.size as usize);
+    bot.download_file(&file.path, &mut photo_bytes).await?;
+
+    let gtin = match tokio::task::spawn_blocking(move || barcode::decode_ean13(&photo_bytes)).await
+    {
+        Ok(Ok(gtin)) => gtin,
+        Ok(Err(e)) => {
+            log::info!("barcode: {}", e);
+            bot.send_message(
+                msg.chat.id,
+                "No barcode found, try again with a sharper photo of the barcode only",
+            )
+            .await?;
+            return Ok(());
+        }
+        Err(e) => {
+            log::error!("Failed to decode barcode {}", e);
+            bot.send_message(msg.chat.id, "Something went wrong, try again later")
+                .await?;
+            return Ok(());
+        }
+    };
+    log::info!("barcode: gtin={}", gtin);
+
+    match ah_client.get_product_by_gtin(&gtin).await? {
+        Some(product) => send_product_card(&bot, msg.chat.id, &pool, &product).await,
+        None => {
+            bot.send_message(
+                msg.chat.id,
+                format!("No product found at AH with barcode {}", gtin),
+            )
             .await?;
+            Ok(())
+        }
+    }
+}
+
+/// Sends a photo of a product with its price, price statistics and the buttons
+/// to track it.
+async fn send_product_card(
+    bot: &Throttle<Bot>,
+    chat_id: ChatId,
+    pool: &SqlitePool,
+    product: &Product,
+) -> HandlerResult {
+    let keyboard = match db::is_tracking_muted(pool, product.id, chat_id.0).await {
+        Ok(Some(muted)) => {
+            create_stop_track_keyboard(product.id).append_row(mute_buttons(product.id, muted))
+        }
+        Ok(None) => create_track_keyboard(product.id),
+        Err(e) => {
+            log::error!("Failed to retrieve tracking of {}: {}", product.id, e);
+            create_track_keyboard(product.id)
+        }
+    };
+
+    let mut caption = format!(
+        "{} - €{} {}",
+        product.title, product.price.now, product.price.unit_size
+    );
+    if let Ok(Some(stats)) = db::get_price_stats(pool, product.id).await {
+        caption.push_str("\n\n");
+        caption.push_str(&stats.summary());
+    }
+
+    match product.images.last() {
+        Some(image) => {
+            bot.send_photo(chat_id, InputFile::url(image.url.clone()))
+                .caption(caption)
+                .reply_markup(keyboard)
+                .disable_notification(true)
+                .await?;
+        }
+        None => {
+            bot.send_message(chat_id, caption)
+                .reply_markup(keyboard)
+                .disable_notification(true)
+                .await?;
+        }
     }
 
     Ok(())
@@ -0,0 +1,494 @@
+use std::{fmt, str::FromStr};
+
+use teloxide::types::{InlineKeyboardButton, InlineKeyboardButtonKind};
+use time::Weekday;
+
+use crate::settings::WEEKDAYS;
+
+/// Version of the callback data format. Buttons sent with another version have
+/// expired, bump it when the meaning of existing data changes.
+const VERSION: &str = "v1";
+
+/// Telegram rejects buttons with more than 64 bytes of callback data
+pub const MAX_DATA_LENGTH: usize = 64;
+
+/// What is watched by a chat, besides single products
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum WatchKind {
+    /// A search query
+    Query,
+    Brand,
+    Taxonomy,
+}
+
+/// Order of the products in `/list`
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ListSort {
+    Name,
+    /// Cheapest first, products without a price last
+    Price,
+    /// Products on bonus first, then by name
+    OnBonus,
+}
+
+impl ListSort {
+    pub const ALL: [ListSort; 3] = [ListSort::Name, ListSort::Price, ListSort::OnBonus];
+
+    fn code(self) -> i64 {
+        match self {
+            ListSort::Name => 0,
+            ListSort::Price => 1,
+            ListSort::OnBonus => 2,
+        }
+    }
+
+    fn from_code(code: i64) -> Option<Self> {
+        Self::ALL.into_iter().find(|sort| sort.code() == code)
+    }
+}
+
+/// Data of an inline keyboard button, Telegram sends it back when the button is
+/// pressed. It is encoded as `v1:<tag>:<arguments>`.
+///
+/// All arguments are integers, so the longest encoding (`SearchPage`) is 47 bytes
+/// and always fits in the callback data of a button.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Callback {
+    Track {
+        product_id: i64,
+    },
+    StopTracking {
+        product_id: i64,
+    },
+    /// Only notify of discounts of at least `min_discount` percent, 0 notifies of
+    /// any discount
+    SetTarget {
+        product_id: i64,
+        min_discount: i64,
+    },
+    /// Ask for a target, see [`crate::dialogue::State::ReceiveTarget`]
+    CustomTarget {
+        product_id: i64,
+    },
+    ConfirmTarget {
+        product_id: i64,
+    },
+    CancelDialogue,
+    /// Follow the brand or the category of a product
+    Follow {
+        kind: WatchKind,
+        product_id: i64,
+    },
+    RemoveWatch {
+        kind: WatchKind,
+        watch_id: i64,
+    },
+    History {
+        product_id: i64,
+    },
+    ShowProduct {
+        product_id: i64,
+    },
+    SearchPage {
+        search_id: i64,
+        page: usize,
+    },
+    /// Show a page of the tracked products, see `/list`
+    ListPage {
+        sort: ListSort,
+        page: usize,
+    },
+    /// Ask to stop tracking all products, `sort` and `page` are used to go back
+    /// to the list
+    UntrackAll {
+        sort: ListSort,
+        page: usize,
+    },
+    ConfirmUntrackAll,
+    /// Mute or unmute the notifications of all tracked products
+    MuteAll {
+        mute: bool,
+        sort: ListSort,
+        page: usize,
+    },
+    /// Turn notifications on a day of the week on or off, see `/settings`
+    ToggleDay {
+        day: Weekday,
+    },
+    SetNotificationHour {
+        hour: i64,
+    },
+    ToggleNothingOnSale,
+    ToggleLastDayReminder,
+    ToggleDigest,
+    /// Stop notifications of a tracked product until it is unmuted
+    Mute {
+        product_id: i64,
+    },
+    /// Stop notifications of a tracked product for one of the [`SNOOZE_WEEKS`]
+    Snooze {
+        product_id: i64,
+        weeks: i64,
+    },
+    Unmute {
+        product_id: i64,
+    },
+}
+
+impl Callback {
+    pub fn button(&self, text: impl Into<String>) -> InlineKeyboardButton {
+        let data = self.to_string();
+        // Telegram would reject the whole message, so never send such a button
+        assert!(
+            data.len() <= MAX_DATA_LENGTH,
+            "callback data too long: {}",
+            data
+        );
+        InlineKeyboardButton::callback(text, data)
+    }
+}
+
+/// Weeks a tracked product can be snoozed for
+pub const SNOOZE_WEEKS: [i64; 2] = [1, 4];
+
+/// Buttons to mute or snooze the notifications of a tracked product, or to unmute
+/// it when it is `muted`. They are shown on notifications and product cards.
+pub fn mute_buttons(product_id: i64, muted: bool) -> Vec<InlineKeyboardButton> {
+    if muted {
+        return vec![Callback::Unmute { product_id }.button("🔔 Unmute")];
+    }
+
+    let snooze = SNOOZE_WEEKS.into_iter().map(|weeks| {
+        let text = match weeks {
+            1 => "💤 1 week".to_string(),
+            weeks => format!("💤 {} weeks", weeks),
+        };
+        Callback::Snooze { product_id, weeks }.button(text)
+    });
+    std::iter::once(Callback::Mute { product_id }.button("🔕 Mute"))
+        .chain(snooze)
+        .collect()
+}
+
+/// Returns `true` if the button is one of the [`mute_buttons`]
+pub fn is_mute_button(button: &InlineKeyboardButton) -> bool {
+    match &button.kind {
+        InlineKeyboardButtonKind::CallbackData(data) => matches!(
+            data.parse::<Callback>(),
+            Ok(Callback::Mute { .. } | Callback::Snooze { .. } | Callback::Unmute { .. })
+        ),
+        _ => false,
+    }
+}
+
+#[derive(Debug, PartialEq)]
+pub enum CallbackError {
+    /// The button was sent by another version of the bot
+    Expired,
+    /// The data was not encoded by the bot
+    Malformed,
+}
+
+impl fmt::Display for CallbackError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CallbackError::Expired => write!(f, "expired callback data"),
+            CallbackError::Malformed => write!(f, "malformed callback data"),
+        }
+    }
+}
+
+impl fmt::Display for Callback {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}:", VERSION)?;
+        match self {
+            Callback::Track { product_id } => write!(f, "t:{}", product_id),
+            Callback::StopTracking { product_id } => write!(f, "s:{}", product_id),
+            Callback::SetTarget {
+                product_id,
+                min_discount,
+            } => write!(f, "st:{}:{}", product_id, min_discount),
+            Callback::CustomTarget { product_id } => write!(f, "ct:{}", product_id),
+            Callback::ConfirmTarget { product_id } => write!(f, "cf:{}", product_id),
+            Callback::CancelDialogue => write!(f, "cd"),
+            Callback::Follow {
+                kind: WatchKind::Taxonomy,
+                product_id,
+            } => write!(f, "ft:{}", product_id),
+            Callback::Follow { product_id, .. } => write!(f, "fb:{}", product_id),
+            Callback::RemoveWatch { kind, watch_id } => match kind {
+                WatchKind::Query => write!(f, "rw:{}", watch_id),
+                WatchKind::Brand => write!(f, "rb:{}", watch_id),
+                WatchKind::Taxonomy => write!(f, "rt:{}", watch_id),
+            },
+            Callback::History { product_id } => write!(f, "h:{}", product_id),
+            Callback::ShowProduct { product_id } => write!(f, "p:{}", product_id),
+            Callback::SearchPage { search_id, page } => write!(f, "sp:{}:{}", search_id, page),
+            Callback::ListPage { sort, page } => write!(f, "lp:{}:{}", sort.code(), page),
+            Callback::UntrackAll { sort, page } => write!(f, "ua:{}:{}", sort.code(), page),
+            Callback::ConfirmUntrackAll => write!(f, "uc"),
+            Callback::MuteAll { mute, sort, page } => {
+                write!(f, "ma:{}:{}:{}", i64::from(*mute), sort.code(), page)
+            }
+            Callback::ToggleDay { day } => write!(f, "sd:{}", day.number_days_from_monday()),
+            Callback::SetNotificationHour { hour } => write!(f, "sh:{}", hour),
+            Callback::ToggleNothingOnSale => write!(f, "sn"),
+            Callback::ToggleLastDayReminder => write!(f, "sl"),
+            Callback::ToggleDigest => write!(f, "sg"),
+            Callback::Mute { product_id } => write!(f, "m:{}", product_id),
+            Callback::Snooze { product_id, weeks } => write!(f, "sz:{}:{}", product_id, weeks),
+            Callback::Unmute { product_id } => write!(f, "um:{}", product_id),
+        }
+    }
+}
+
+impl FromStr for Callback {
+    type Err = CallbackError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if s.len() > MAX_DATA_LENGTH {
+            return Err(CallbackError::Malformed);
+        }
+
+        let mut parts = s.split(':');
+        if parts.next() != Some(VERSION) {
+            // buttons sent before the data was versioned start with a number
+            return Err(CallbackError::Expired);
+        }
+        let tag = parts.next().ok_or(CallbackError::Malformed)?;
+        let mut next = || {
+            parts
+                .next()
+                .and_then(|part| part.parse::<i64>().ok())
+                .ok_or(CallbackError::Malformed)
+        };
+        let page = |page: i64| usize::try_from(page).map_err(|_| CallbackError::Malformed);
+        let sort = |code: i64| ListSort::from_code(code).ok_or(CallbackError::Malformed);
+
+        let callback = match tag {
+            "t" => Callback::Track {
+                product_id: next()?,
+            },
+            "s" => Callback::StopTracking {
+                product_id: next()?,
+            },
+            "st" => Callback::SetTarget {
+                product_id: next()?,
+                min_discount: Some(next()?)
+                    .filter(|min_discount| (0..=100).contains(min_discount))
+                    .ok_or(CallbackError::Malformed)?,
+            },
+            "ct" => Callback::CustomTarget {
+                product_id: next()?,
+            },
+            "cf" => Callback::ConfirmTarget {
+                product_id: next()?,
+            },
+            "cd" => Callback::CancelDialogue,
+            "fb" => Callback::Follow {
+                kind: WatchKind::Brand,
+                product_id: next()?,
+            },
+            "ft" => Callback::Follow {
+                kind: WatchKind::Taxonomy,
+                product_id: next()?,
+            },
+            "rw" => Callback::RemoveWatch {
+                kind: WatchKind::Query,
+                watch_id: next()?,
+            },
+            "rb" => Callback::RemoveWatch {
+                kind: WatchKind::Brand,
+                watch_id: next()?,
+            },
+            "rt" => Callback::RemoveWatch {
+                kind: WatchKind::Taxonomy,
+                watch_id: next()?,
+            },
+            "h" => Callback::History {
+                product_id: next()?,
+            },
+            "p" => Callback::ShowProduct {
+                product_id: next()?,
+            },
+            "sp" => Callback::SearchPage {
+                search_id: next()?,
+                page: page(next()?)?,
+            },
+            "lp" => Callback::ListPage {
+                sort: sort(next()?)?,
+                page: page(next()?)?,
+            },
+            "ua" => Callback::UntrackAll {
+                sort: sort(next()?)?,
+                page: page(next()?)?,
+            },
+            "uc" => Callback::ConfirmUntrackAll,
+            "ma" => Callback::MuteAll {
+                mute: match next()? {
+                    0 => false,
+                    1 => true,
+                    _ => return Err(CallbackError::Malformed),
+                },
+                sort: sort(next()?)?,
+                page: page(next()?)?,
+            },
+            "sd" => Callback::ToggleDay {
+                day: usize::try_from(next()?)
+                    .ok()
+                    .and_then(|day| WEEKDAYS.get(day).copied())
+                    .ok_or(CallbackError::Malformed)?,
+            },
+            "sh" => Callback::SetNotificationHour {
+                hour: Some(next()?)
+                    .filter(|hour| (0..24).contains(hour))
+                    .ok_or(CallbackError::Malformed)?,
+            },
+            "sn" => Callback::ToggleNothingOnSale,
+            "sl" => Callback::ToggleLastDayReminder,
+            "sg" => Callback::ToggleDigest,
+            "m" => Callback::Mute {
+                product_id: next()?,
+            },
+            "sz" => Callback::Snooze {
+                product_id: next()?,
+                weeks: Some(next()?)
+                    .filter(|weeks| SNOOZE_WEEKS.contains(weeks))
+                    .ok_or(CallbackError::Malformed)?,
+            },
+            "um" => Callback::Unmute {
+                product_id: next()?,
+            },
+            _ => return Err(CallbackError::Malformed),
+        };
+
+        if parts.next().is_some() {
+            return Err(CallbackError::Malformed);
+        }
+        Ok(callback)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn all_callbacks() -> Vec<Callback> {
+        let mut callbacks = vec![
+            Callback::Track { product_id: 1 },
+            Callback::StopTracking { product_id: 2 },
+            Callback::SetTarget {
+                product_id: 3,
+                min_discount: 0,
+            },
+            Callback::SetTarget {
+                product_id: 3,
+                min_discount: 100,
+            },
+            Callback::CustomTarget { product_id: 4 },
+            Callback::ConfirmTarget { product_id: 5 },
+            Callback::CancelDialogue,
+            Callback::History { product_id: 6 },
+            Callback::ShowProduct { product_id: 7 },
+            Callback::SearchPage {
+                search_id: i64::MAX,
+                page: usize::try_from(i64::MAX).unwrap(),
+            },
+            Callback::ConfirmUntrackAll,
+            Callback::SetNotificationHour { hour: 0 },
+            Callback::SetNotificationHour { hour: 23 },
+            Callback::ToggleNothingOnSale,
+            Callback::ToggleLastDayReminder,
+            Callback::ToggleDigest,
+            Callback::Mute { product_id: 8 },
+            Callback::Unmute { product_id: 9 },
+        ];
+        for kind in [WatchKind::Brand, WatchKind::Taxonomy] {
+            callbacks.push(Callback::Follow {
+                kind,
+                product_id: 10,
+            });
+        }
+        for kind in [WatchKind::Query, WatchKind::Brand, WatchKind::Taxonomy] {
+            callbacks.push(Callback::RemoveWatch { kind, watch_id: 11 });
+        }
+        for sort in ListSort::ALL {
+            callbacks.push(Callback::ListPage { sort, page: 12 });
+            callbacks.push(Callback::UntrackAll { sort, page: 13 });
+            for mute in [false, true] {
+                callbacks.push(Callback::MuteAll {
+                    mute,
+                    sort,
+                    page: 14,
+                });
+            }
+        }
+        for day in WEEKDAYS {
+            callbacks.push(Callback::ToggleDay { day });
+        }
+        for weeks in SNOOZE_WEEKS {
+            callbacks.push(Callback::Snooze {
+                product_id: 15,
+                weeks,
+            });
+        }
+        callbacks
+    }
+
+    #[test]
+    fn round_trips_every_variant() {
+        for callback in all_callbacks() {
+            let data = callback.to_string();
+            assert!(data.len() <= MAX_DATA_LENGTH, "too long: {}", data);
+            assert_eq!(data.parse::<Callback>(), Ok(callback), "data: {}", data);
+        }
+    }
+
+    #[test]
+    fn rejects_expired_data() {
+        for data in ["v0:t:1", "12345", "t:1", ""] {
+            assert_eq!(
+                data.parse::<Callback>(),
+                Err(CallbackError::Expired),
+                "data: {}",
+                data
+            );
+        }
+    }
+
+    #[test]
+    fn rejects_trailing_arguments() {
+        for callback in all_callbacks() {
+            let data = format!("{}:1", callback);
+            assert_eq!(
+                data.parse::<Callback>(),
+                Err(CallbackError::Malformed),
+                "data: {}",
+                data
+            );
+        }
+    }
+
+    #[test]
+    fn rejects_out_of_range_arguments() {
+        for data in [
+            "v1:st:1:-1",
+            "v1:st:1:101",
+            "v1:sz:1:0",
+            "v1:sz:1:2",
+            "v1:sz:1:9223372036854775807",
+            "v1:sh:24",
+            "v1:sd:7",
+            "v1:lp:3:0",
+            "v1:sp:1:-1",
+            "v1:ma:2:0:0",
+        ] {
+            assert_eq!(
+                data.parse::<Callback>(),
+                Err(CallbackError::Malformed),
+                "data: {}",
+                data
+            );
+        }
+    }
+}
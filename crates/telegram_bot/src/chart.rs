@@ -0,0 +1,133 @@
+use std::{collections::BTreeSet, fmt, io::Cursor, sync::Once};
+
+use image::{ImageFormat, RgbImage};
+use plotters::{
+    prelude::*,
+    style::{register_font, FontStyle},
+};
+use time::Date;
+
+use crate::db::PriceHistory;
+
+const WIDTH: u32 = 800;
+const HEIGHT: u32 = 480;
+const FONT: &[u8] = include_bytes!("../assets/DejaVuSans.ttf");
+const BONUS_COLOR: RGBColor = RGBColor(255, 121, 0);
+
+#[derive(Debug)]
+pub enum ChartError {
+    /// There are no prices to draw
+    NoData,
+    Drawing(String),
+    Encoding(image::ImageError),
+}
+
+impl fmt::Display for ChartError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ChartError::NoData => write!(f, "no price history"),
+            ChartError::Drawing(e) => write!(f, "failed to draw chart: {}", e),
+            ChartError::Encoding(e) => write!(f, "failed to encode chart: {}", e),
+        }
+    }
+}
+
+impl<E: std::error::Error + Send + Sync> From<DrawingAreaErrorKind<E>> for ChartError {
+    fn from(e: DrawingAreaErrorKind<E>) -> Self {
+        ChartError::Drawing(e.to_string())
+    }
+}
+
+/// Plotters has no access to system fonts without native dependencies, so the
+/// bundled font has to be registered before drawing any text.
+fn register_bundled_font() {
+    static REGISTER: Once = Once::new();
+    REGISTER.call_once(|| {
+        if register_font("sans-serif", FontStyle::Normal, FONT).is_err() {
+            log::error!("Failed to register bundled font");
+        }
+    });
+}
+
+/// Renders a PNG line chart of the price of a product over time, with bonus
+/// periods shaded in the background.
+pub fn render_price_history(title: &str, history: &[PriceHistory]) -> Result<Vec<u8>, ChartError> {
+    let (Some(first), Some(last)) = (history.first(), history.last()) else {
+        return Err(ChartError::NoData);
+    };
+    register_bundled_font();
+
+    let first_day = first.date.to_julian_day();
+    let last_day = last.date.to_julian_day().max(first_day + 1);
+    let max_price = history.iter().map(|row| row.price).max().unwrap_or(0) as f64 / 100.0;
+    let max_price = (max_price * 1.2).max(1.0);
+
+    // the same bonus period is stored in every history row fetched during it
+    let bonus_periods = history
+        .iter()
+        .filter_map(|row| Some((row.discount_start_date?, row.discount_end_date?)))
+        .map(|(start, end)| {
+            (
+                start.to_julian_day().clamp(first_day, last_day),
+                end.to_julian_day().clamp(first_day, last_day),
+            )
+        })
+        .collect::<BTreeSet<_>>();
+
+    let mut buffer = vec![0; (WIDTH * HEIGHT * 3) as usize];
+    {
+        let root = BitMapBackend::with_buffer(&mut buffer, (WIDTH, HEIGHT)).into_drawing_area();
+        root.fill(&WHITE)?;
+
+        let mut chart = ChartBuilder::on(&root)
+            .caption(title, ("sans-serif", 24))
+            .margin(16)
+            .x_label_area_size(32)
+            .y_label_area_size(64)
+            .build_cartesian_2d(first_day..last_day, 0.0..max_price)?;
+        chart
+            .configure_mesh()
+            .x_labels(6)
+            .x_label_formatter(&|day| {
+                Date::from_julian_day(*day)
+                    .map(|date| date.to_string())
+                    .unwrap_or_default()
+            })
+            .y_label_formatter(&|price| format!("€{:.2}", price))
+            .draw()?;
+
+        chart
+            .draw_series(bonus_periods.iter().map(|(start, end)| {
+                Rectangle::new(
+                    [(*start, 0.0), (*end, max_price)],
+                    BONUS_COLOR.mix(0.25).filled(),
+                )
+            }))?
+            .label("Bonus")
+            .legend(|(x, y)| Rectangle::new([(x, y - 5), (x + 10, y + 5)], BONUS_COLOR.filled()));
+        chart
+            .draw_series(LineSeries::new(
+                history
+                    .iter()
+                    .map(|row| (row.date.to_julian_day(), row.price as f64 / 100.0)),
+                BLUE.stroke_width(2),
+            ))?
+            .label("Price")
+            .legend(|(x, y)| PathElement::new([(x, y), (x + 10, y)], BLUE.stroke_width(2)));
+        chart
+            .configure_series_labels()
+            .background_style(WHITE.mix(0.8))
+            .border_style(BLACK)
+            .draw()?;
+
+        root.present()?;
+    }
+
+    let image = RgbImage::from_raw(WIDTH, HEIGHT, buffer)
+        .ok_or_else(|| ChartError::Drawing("buffer has the wrong size".to_string()))?;
+    let mut png = Cursor::new(Vec::new());
+    image
+        .write_to(&mut png, ImageFormat::Png)
+        .map_err(ChartError::Encoding)?;
+    Ok(png.into_inner())
+}
@@ -1,19 +1,26 @@
 use ah_api::product::Product;
 
-use sqlx::{sqlite::SqliteQueryResult, Error, SqlitePool};
+use sqlx::{sqlite::SqliteQueryResult, AssertSqlSafe, Error, SqlitePool};
 use teloxide::utils::markdown::escape;
-use time::Date;
+use time::{Date, OffsetDateTime, PrimitiveDateTime};
+
+use crate::{
+    format::format_price,
+    prediction::{self, WeekPrediction},
+    settings::ChatSettings,
+    target::Target,
+};
 
 pub async fn insert_product(
     pool: &SqlitePool,
     product: &Product,
 ) -> Result<SqliteQueryResult, Error> {
+    // products without an image are listed without a photo
     let image_url = product
         .images
         .last()
-        .expect("There should be an image")
-        .url
-        .to_string();
+        .map(|image| image.url.to_string())
+        .unwrap_or_default();
 
     sqlx::query_file!(
         "src/queries/insert_product.sql",
@@ -34,6 +41,8 @@ pub async fn insert_product_history(
 ) -> Result<SqliteQueryResult, Error> {
     let price = product.get_price_for_db();
     let discount_text = product.get_discount_text();
+    let unit_price = product.get_unit_price_for_db();
+    let price_was = product.get_price_was_for_db();
     let (start, end) = match product.discount {
         None => (None, None),
         Some(ref discount) => (
@@ -48,11 +57,33 @@ pub async fn insert_product_history(
         discount_text,
         start,
         end,
+        product.price.unit_size,
+        unit_price,
+        price_was,
     )
     .execute(pool)
     .await
 }
 
+pub struct LatestPrice {
+    pub price: i64,
+    pub discount: Option<String>,
+}
+
+/// Returns the last recorded price of a product, `None` if it was never fetched.
+pub async fn get_latest_price(
+    pool: &SqlitePool,
+    product_id: i64,
+) -> Result<Option<LatestPrice>, Error> {
+    sqlx::query_as!(
+        LatestPrice,
+        "SELECT price, discount FROM products_history WHERE product_id = ? ORDER BY id DESC LIMIT 1",
+        product_id
+    )
+    .fetch_optional(pool)
+    .await
+}
+
 pub async fn insert_product_tracking(
     pool: &SqlitePool,
     product_id: i64,
@@ -63,6 +94,31 @@ pub async fn insert_product_tracking(
         .await
 }
 
+/// Sets the notification target of a tracked product. Returns the number of rows
+/// updated, which is 0 if the chat doesn't track the product.
+pub async fn set_tracking_target(
+    pool: &SqlitePool,
+    product_id: i64,
+    chat_id: i64,
+    target: &Target,
+) -> Result<u64, Error> {
+    let (target_price, min_discount) = match *target {
+        Target::AnyDiscount => (None, None),
+        Target::Price(cents) => (Some(cents), None),
+        Target::MinDiscount(percentage) => (None, Some(percentage)),
+    };
+    sqlx::query_file!(
+        "src/queries/update_tracking_target.sql",
+        product_id,
+        chat_id,
+        target_price,
+        min_discount
+    )
+    .execute(pool)
+    .await
+    .map(|result| result.rows_affected())
+}
+
 pub async fn delete_product_tracking(
     pool: &SqlitePool,
     product_id: i64,
@@ -77,12 +133,106 @@ pub async fn delete_product_tracking(
     .await
 }
 
-pub async fn get_all_product_ids(pool: &SqlitePool) -> Result<Vec<i64>, Error> {
-    sqlx::query_scalar!("SELECT id FROM products")
+/// Stops tracking all products of a chat. Returns the number of products that
+/// were tracked.
+pub async fn delete_all_product_trackings(pool: &SqlitePool, chat_id: i64) -> Result<u64, Error> {
+    sqlx::query_file!("src/queries/delete_all_product_trackings.sql", chat_id)
+        .execute(pool)
+        .await
+        .map(|result| result.rows_affected())
+}
+
+/// Mutes a tracked product when `send_notification` is `false`, or snoozes it
+/// until `muted_until`. Returns the number of rows updated, which is 0 if the chat
+/// doesn't track the product.
+pub async fn set_tracking_mute(
+    pool: &SqlitePool,
+    product_id: i64,
+    chat_id: i64,
+    send_notification: bool,
+    muted_until: Option<Date>,
+) -> Result<u64, Error> {
+    sqlx::query_file!(
+        "src/queries/update_tracking_mute.sql",
+        product_id,
+        chat_id,
+        send_notification,
+        muted_until
+    )
+    .execute(pool)
+    .await
+    .map(|result| result.rows_affected())
+}
+
+/// Returns whether a tracked product is muted or snoozed, `None` if the chat
+/// doesn't track the product.
+pub async fn is_tracking_muted(
+    pool: &SqlitePool,
+    product_id: i64,
+    chat_id: i64,
+) -> Result<Option<bool>, Error> {
+    sqlx::query_scalar!(
+        r#"SELECT (NOT send_notification OR COALESCE(muted_until > DATE('now'), FALSE)) AS "muted!: bool"
+        FROM tracked_products
+        WHERE product_id = ? AND chat_id = ?"#,
+        product_id,
+        chat_id
+    )
+    .fetch_optional(pool)
+    .await
+}
+
+/// Turns the notifications of all tracked products of a chat on or off, this
+/// also ends snoozes.
+pub async fn set_tracking_notifications(
+    pool: &SqlitePool,
+    chat_id: i64,
+    send_notification: bool,
+) -> Result<SqliteQueryResult, Error> {
+    sqlx::query_file!(
+        "src/queries/update_tracking_notifications.sql",
+        chat_id,
+        send_notification
+    )
+    .execute(pool)
+    .await
+}
+
+/// Returns the chat IDs of everyone tracking the given product, except the ones
+/// who muted or snoozed it.
+pub async fn get_product_trackers(pool: &SqlitePool, product_id: i64) -> Result<Vec<i64>, Error> {
+    sqlx::query_scalar!(
+        "SELECT chat_id FROM tracked_products
+        WHERE product_id = ?
+          AND send_notification
+          AND (muted_until IS NULL OR muted_until <= DATE('now'))",
+        product_id
+    )
+    .fetch_all(pool)
+    .await
+}
+
+/// Returns the IDs of the products tracked by at least one active chat, the
+/// prices of other products are not fetched anymore.
+pub async fn get_product_ids_to_fetch(pool: &SqlitePool) -> Result<Vec<i64>, Error> {
+    sqlx::query_file_scalar!("src/queries/select_product_ids_to_fetch.sql")
         .fetch_all(pool)
         .await
 }
 
+/// Deletes the products no active chat tracks and whose prices were not fetched
+/// for `retention_days`, with their price history. Returns the number of products
+/// deleted.
+pub async fn delete_untracked_products(
+    pool: &SqlitePool,
+    retention_days: i64,
+) -> Result<u64, Error> {
+    sqlx::query_file!("src/queries/delete_untracked_products.sql", retention_days)
+        .execute(pool)
+        .await
+        .map(|result| result.rows_affected())
+}
+
 /// Returns a list of product IDs that are tracked by the given chat ID.
 pub async fn get_all_tracked_products_ids(
     pool: &SqlitePool,
@@ -101,6 +251,19 @@ pub struct TrackedProduct {
     pub name: String,
     pub url: String,
     pub image_url: String,
+    /// Latest recorded price in cents, `None` until the price is first fetched
+    pub price: Option<i64>,
+    pub on_bonus: bool,
+    /// Text of the current or upcoming discount
+    pub discount: Option<String>,
+    pub discount_start_date: Option<Date>,
+    pub discount_end_date: Option<Date>,
+    /// When the price was last fetched, in UTC
+    pub checked_at: Option<PrimitiveDateTime>,
+    /// `false` if the product is muted
+    pub send_notification: bool,
+    /// Notifications are snoozed until this date
+    pub muted_until: Option<Date>,
 }
 
 pub async fn get_all_tracked_products(
@@ -122,14 +285,44 @@ pub struct NotificationDiscount {
     pub image_url: String,
     pub product_id: i64,
     pub discount: String,
-    pub future_discount: i32,
+    pub future_discount: i64,
     pub discount_start_date: time::Date,
     pub discount_end_date: time::Date,
     pub price: i64,
+    /// Regular price, `None` if AH didn't return it
+    pub price_was: Option<i64>,
     pub chat_id: i64,
 }
 
 impl NotificationDiscount {
+    /// Returns the discount in cents
+    pub fn savings(&self) -> i64 {
+        self.price_was
+            .map_or(0, |price_was| (price_was - self.price).max(0))
+    }
+
+    /// Returns a markdown formatted line of a digest of discounts.
+    pub fn digest_line(&self) -> String {
+        let when = if self.future_discount == 1 {
+            format!(" from {}", self.discount_start_date)
+        } else {
+            String::new()
+        };
+        let savings = match self.savings() {
+            0 => String::new(),
+            savings => format!(", save {}", format_price(savings)),
+        };
+        format!(
+            "• [{}](https://www.ah.nl{}){}: {} for {}{}",
+            escape(self.name.as_str()),
+            self.url,
+            escape(when.as_str()),
+            escape(self.discount.as_str()),
+            escape(format_price(self.price).as_str()),
+            escape(savings.as_str())
+        )
+    }
+
     /// Returns a markdown formatted message for the Telegram bot.
     pub fn message(&self) -> String {
         if self.future_discount == 1 {
@@ -150,8 +343,20 @@ impl NotificationDiscount {
             )
         }
     }
+
+    /// Returns a markdown formatted reminder for the last day of the discount.
+    pub fn last_day_message(&self) -> String {
+        format!(
+            "Last day of the discount on [{}](https://www.ah.nl{}): {}",
+            escape(self.name.as_str()),
+            self.url,
+            escape(self.discount.as_str())
+        )
+    }
 }
 
+/// Returns the discounts of tracked products that the trackers were not notified
+/// of yet.
 pub async fn get_discounted_products(
     pool: &SqlitePool,
 ) -> Result<Vec<NotificationDiscount>, Error> {
@@ -163,6 +368,135 @@ pub async fn get_discounted_products(
     .await
 }
 
+/// Returns the discounts of tracked products ending on `date` that the trackers
+/// were not reminded of yet.
+pub async fn get_last_day_discounts(
+    pool: &SqlitePool,
+    date: Date,
+) -> Result<Vec<NotificationDiscount>, Error> {
+    sqlx::query_file_as!(
+        NotificationDiscount,
+        "src/queries/select_last_day_discounts.sql",
+        date
+    )
+    .fetch_all(pool)
+    .await
+}
+
+/// Notification sent for a discount of a tracked product
+#[derive(Debug, Clone, Copy)]
+pub enum NotificationKind {
+    /// The discount was announced
+    Bonus,
+    /// Reminder on the last day of the discount
+    LastDay,
+}
+
+impl NotificationKind {
+    fn as_str(self) -> &'static str {
+        match self {
+            NotificationKind::Bonus => "bonus",
+            NotificationKind::LastDay => "last_day",
+        }
+    }
+}
+
+/// Records that a chat was notified of a discount. Fails with a unique violation
+/// if the chat was already sent this kind of notification for the same discount.
+/// `chat_id` is passed separately as the chat may have migrated since the
+/// discount was queried.
+pub async fn insert_notification_sent(
+    pool: &SqlitePool,
+    chat_id: i64,
+    notification: &NotificationDiscount,
+    kind: NotificationKind,
+) -> Result<SqliteQueryResult, Error> {
+    let kind = kind.as_str();
+    sqlx::query_file!(
+        "src/queries/insert_notification_sent.sql",
+        chat_id,
+        notification.product_id,
+        notification.discount_start_date,
+        notification.discount_end_date,
+        kind
+    )
+    .execute(pool)
+    .await
+}
+
+pub struct PriceDrop {
+    pub product_id: i64,
+    pub name: String,
+    pub url: String,
+    pub image_url: String,
+    pub price: i64,
+    pub previous_price: i64,
+    pub lowest_previous_price: i64,
+    pub first_recorded_at: PrimitiveDateTime,
+    /// When the dropped price was fetched
+    pub recorded_at: PrimitiveDateTime,
+    pub chat_id: i64,
+}
+
+impl PriceDrop {
+    /// Returns `true` if the price is the lowest one recorded for the product.
+    pub fn is_lowest_price(&self) -> bool {
+        self.price <= self.lowest_previous_price
+    }
+
+    /// Returns the number of whole months the price history of the product covers.
+    pub fn months_recorded(&self) -> i64 {
+        let now = OffsetDateTime::now_utc();
+        let recorded = PrimitiveDateTime::new(now.date(), now.time()) - self.first_recorded_at;
+        recorded.whole_days() / 30
+    }
+
+    /// Returns a markdown formatted message for the Telegram bot.
+    pub fn message(&self) -> String {
+        match lowest_price_period(self.months_recorded()) {
+            Some(period) if self.is_lowest_price() => format!(
+                "[{}](https://www.ah.nl{}) is at its lowest price in {}: {}",
+                escape(self.name.as_str()),
+                self.url,
+                period,
+                escape(format_price(self.price).as_str())
+            ),
+            _ => format!(
+                "[{}](https://www.ah.nl{}) dropped in price from {} to {}",
+                escape(self.name.as_str()),
+                self.url,
+                escape(format_price(self.previous_price).as_str()),
+                escape(format_price(self.price).as_str())
+            ),
+        }
+    }
+}
+
+/// Months of price history needed before a price is called the lowest one
+const LOWEST_PRICE_MIN_MONTHS: i64 = 3;
+
+/// Returns how long a price is the lowest one, e.g. `5 months` or `a year`, or
+/// `None` when the history is too short to tell.
+fn lowest_price_period(months: i64) -> Option<String> {
+    match months {
+        months if months < LOWEST_PRICE_MIN_MONTHS => None,
+        months if months < 12 => Some(format!("{} months", months)),
+        months if months < 24 => Some("a year".to_string()),
+        months => Some(format!("{} years", months / 12)),
+    }
+}
+
+/// Returns tracked products whose regular price, i.e. outside of a discount, dropped
+/// compared to the previous history row, for history rows created after `since`.
+pub async fn get_price_drops(
+    pool: &SqlitePool,
+    since: PrimitiveDateTime,
+) -> Result<Vec<PriceDrop>, Error> {
+    sqlx::query_file_as!(PriceDrop, "src/queries/select_price_drops.sql", since)
+        .fetch_all(pool)
+        .await
+}
+
 /// Returns a list of chat IDs that track products that are not on discount.
 /// This is used to send a message to the user that none of the products they
 /// track are on discount.
@@ -171,3 +505,685 @@ pub async fn get_users_not_notified(pool: &SqlitePool) -> Result<Vec<i64>, Error
         .fetch_all(pool)
         .await
 }
+
+pub struct Shrinkflation {
+    pub product_id: i64,
+    pub name: String,
+    pub url: String,
+    pub previous_unit_size: String,
+    pub unit_size: String,
+    pub previous_unit_price: i64,
+    pub unit_price: i64,
+    pub detected_at: PrimitiveDateTime,
+}
+
+impl Shrinkflation {
+    /// Returns a markdown formatted message for the Telegram bot.
+    pub fn message(&self) -> String {
+        format!(
+            "[{}](https://www.ah.nl{}) got smaller: {} → {}, price per unit went up from {} to {}",
+            escape(self.name.as_str()),
+            self.url,
+            escape(self.previous_unit_size.as_str()),
+            escape(self.unit_size.as_str()),
+            escape(format_price(self.previous_unit_price).as_str()),
+            escape(format_price(self.unit_price).as_str()),
+        )
+    }
+}
+
+/// Returns products whose pack size changed while their price per unit went up,
+/// detected after `since`. See [`ah_api::diff::ProductDiff::is_shrinkflation`].
+pub async fn get_shrinkflation(
+    pool: &SqlitePool,
+    since: PrimitiveDateTime,
+) -> Result<Vec<Shrinkflation>, Error> {
+    sqlx::query_file_as!(Shrinkflation, "src/queries/select_shrinkflation.sql", since)
+        .fetch_all(pool)
+        .await
+}
+
+pub struct Watch {
+    pub id: i64,
+    pub chat_id: i64,
+    pub query: String,
+}
+
+pub async fn insert_watch(
+    pool: &SqlitePool,
+    chat_id: i64,
+    query: &str,
+) -> Result<SqliteQueryResult, Error> {
+    sqlx::query_file!("src/queries/insert_watch.sql", chat_id, query)
+        .execute(pool)
+        .await
+}
+
+pub async fn delete_watch(
+    pool: &SqlitePool,
+    watch_id: i64,
+    chat_id: i64,
+) -> Result<SqliteQueryResult, Error> {
+    sqlx::query_file!("src/queries/delete_watch.sql", watch_id, chat_id)
+        .execute(pool)
+        .await
+}
+
+/// Returns the saved searches of the given chat ID.
+pub async fn get_watches(pool: &SqlitePool, chat_id: i64) -> Result<Vec<Watch>, Error> {
+    sqlx::query_as!(
+        Watch,
+        "SELECT id, chat_id, query FROM watches WHERE chat_id = ? ORDER BY query",
+        chat_id
+    )
+    .fetch_all(pool)
+    .await
+}
+
+pub async fn get_all_watches(pool: &SqlitePool) -> Result<Vec<Watch>, Error> {
+    sqlx::query_as!(Watch, "SELECT id, chat_id, query FROM watches")
+        .fetch_all(pool)
+        .await
+}
+
+/// Records that a chat was notified of a discounted product found by one of its
+/// watches. Fails with a unique violation if the chat was already notified of the
+/// same discount period.
+pub async fn insert_watch_notification(
+    pool: &SqlitePool,
+    chat_id: i64,
+    product_id: i64,
+    discount_end_date: &str,
+) -> Result<SqliteQueryResult, Error> {
+    sqlx::query_file!(
+        "src/queries/insert_watch_notification.sql",
+        chat_id,
+        product_id,
+        discount_end_date
+    )
+    .execute(pool)
+    .await
+}
+
+/// Forgets a watch match, so it is sent again when sending it failed.
+pub async fn delete_watch_notification(
+    pool: &SqlitePool,
+    chat_id: i64,
+    product_id: i64,
+    discount_end_date: &str,
+) -> Result<SqliteQueryResult, Error> {
+    sqlx::query_file!(
+        "src/queries/delete_watch_notification.sql",
+        chat_id,
+        product_id,
+        discount_end_date
+    )
+    .execute(pool)
+    .await
+}
+
+pub struct BrandWatch {
+    pub id: i64,
+    pub chat_id: i64,
+    pub brand: String,
+}
+
+pub async fn insert_brand_watch(
+    pool: &SqlitePool,
+    chat_id: i64,
+    brand: &str,
+) -> Result<SqliteQueryResult, Error> {
+    sqlx::query_file!("src/queries/insert_brand_watch.sql", chat_id, brand)
+        .execute(pool)
+        .await
+}
+
+pub async fn delete_brand_watch(
+    pool: &SqlitePool,
+    watch_id: i64,
+    chat_id: i64,
+) -> Result<SqliteQueryResult, Error> {
+    sqlx::query_file!("src/queries/delete_brand_watch.sql", watch_id, chat_id)
+        .execute(pool)
+        .await
+}
+
+/// Returns the brands followed by the given chat ID.
+pub async fn get_brand_watches(pool: &SqlitePool, chat_id: i64) -> Result<Vec<BrandWatch>, Error> {
+    sqlx::query_as!(
+        BrandWatch,
+        "SELECT id, chat_id, brand FROM brand_watches WHERE chat_id = ? ORDER BY brand",
+        chat_id
+    )
+    .fetch_all(pool)
+    .await
+}
+
+pub async fn get_all_brand_watches(pool: &SqlitePool) -> Result<Vec<BrandWatch>, Error> {
+    sqlx::query_as!(BrandWatch, "SELECT id, chat_id, brand FROM brand_watches")
+        .fetch_all(pool)
+        .await
+}
+
+pub struct TaxonomyWatch {
+    pub id: i64,
+    pub chat_id: i64,
+    pub taxonomy_id: i64,
+    pub taxonomy_name: String,
+}
+
+pub async fn insert_taxonomy_watch(
+    pool: &SqlitePool,
+    chat_id: i64,
+    taxonomy_id: i64,
+    taxonomy_name: &str,
+) -> Result<SqliteQueryResult, Error> {
+    sqlx::query_file!(
+        "src/queries/insert_taxonomy_watch.sql",
+        chat_id,
+        taxonomy_id,
+        taxonomy_name
+    )
+    .execute(pool)
+    .await
+}
+
+pub async fn delete_taxonomy_watch(
+    pool: &SqlitePool,
+    watch_id: i64,
+    chat_id: i64,
+) -> Result<SqliteQueryResult, Error> {
+    sqlx::query_file!("src/queries/delete_taxonomy_watch.sql", watch_id, chat_id)
+        .execute(pool)
+        .await
+}
+
+/// Returns the categories followed by the given chat ID.
+pub async fn get_taxonomy_watches(
+    pool: &SqlitePool,
+    chat_id: i64,
+) -> Result<Vec<TaxonomyWatch>, Error> {
+    sqlx::query_as!(
+        TaxonomyWatch,
+        "SELECT id, chat_id, taxonomy_id, taxonomy_name FROM taxonomy_watches WHERE chat_id = ? ORDER BY taxonomy_name",
+        chat_id
+    )
+    .fetch_all(pool)
+    .await
+}
+
+pub async fn get_all_taxonomy_watches(pool: &SqlitePool) -> Result<Vec<TaxonomyWatch>, Error> {
+    sqlx::query_as!(
+        TaxonomyWatch,
+        "SELECT id, chat_id, taxonomy_id, taxonomy_name FROM taxonomy_watches"
+    )
+    .fetch_all(pool)
+    .await
+}
+
+pub struct PriceHistory {
+    pub date: Date,
+    pub price: i64,
+    pub discount_start_date: Option<Date>,
+    pub discount_end_date: Option<Date>,
+}
+
+/// Returns all recorded prices of a product, oldest first.
+pub async fn get_price_history(
+    pool: &SqlitePool,
+    product_id: i64,
+) -> Result<Vec<PriceHistory>, Error> {
+    sqlx::query_file_as!(
+        PriceHistory,
+        "src/queries/select_price_history.sql",
+        product_id
+    )
+    .fetch_all(pool)
+    .await
+}
+
+pub async fn get_product_name(pool: &SqlitePool, product_id: i64) -> Result<Option<String>, Error> {
+    sqlx::query_scalar!("SELECT name FROM products WHERE id = ?", product_id)
+        .fetch_optional(pool)
+        .await
+}
+
+pub struct BonusPeriod {
+    pub start_date: Date,
+    pub end_date: Date,
+}
+
+/// Returns the distinct bonus periods recorded for a product, oldest first.
+pub async fn get_bonus_periods(
+    pool: &SqlitePool,
+    product_id: i64,
+) -> Result<Vec<BonusPeriod>, Error> {
+    sqlx::query_file_as!(
+        BonusPeriod,
+        "src/queries/select_bonus_periods.sql",
+        product_id
+    )
+    .fetch_all(pool)
+    .await
+}
+
+struct PriceStatsRow {
+    min_price: Option<i64>,
+    max_price: Option<i64>,
+    average_price: Option<f64>,
+    last_bonus_date: Option<Date>,
+    bonus_weeks: i64,
+    average_discount: Option<f64>,
+}
+
+pub struct PriceStats {
+    pub min_price: i64,
+    pub max_price: i64,
+    pub average_price: f64,
+    pub last_bonus_date: Option<Date>,
+    /// Number of weeks out of the last 52 in which the product was on bonus
+    pub bonus_weeks: i64,
+    /// Average discount percentage of the bonus periods with a known regular price
+    pub average_discount: Option<f64>,
+    pub next_bonus: Option<WeekPrediction>,
+}
+
+impl PriceStats {
+    /// Returns a plain text summary of the stats, shown below product captions.
+    pub fn summary(&self) -> String {
+        let mut lines = vec![format!(
+            "Lowest {} · highest {} · average {}",
+            format_price(self.min_price),
+            format_price(self.max_price),
+            format_price(self.average_price.round() as i64)
+        )];
+
+        let mut bonus = format!("On bonus {} of the last 52 weeks", self.bonus_weeks);
+        if let Some(last_bonus_date) = self.last_bonus_date {
+            bonus.push_str(&format!(", last until {}", last_bonus_date));
+        }
+        if let Some(average_discount) = self.average_discount {
+            bonus.push_str(&format!(", on average {:.0}% off", average_discount));
+        }
+        lines.push(bonus);
+
+        if let Some(next_bonus) = &self.next_bonus {
+            lines.push(format!(
+                "Next bonus expected in the week of {} ({:.0}% chance)",
+                next_bonus.week_start,
+                next_bonus.probability * 100.0
+            ));
+        }
+        lines.join("\n")
+    }
+}
+
+/// Returns price statistics of a product, or `None` if no price was recorded yet.
+pub async fn get_price_stats(
+    pool: &SqlitePool,
+    product_id: i64,
+) -> Result<Option<PriceStats>, Error> {
+    let row = sqlx::query_file_as!(
+        PriceStatsRow,
+        "src/queries/select_price_stats.sql",
+        product_id
+    )
+    .fetch_one(pool)
+    .await?;
+    let (Some(min_price), Some(max_price), Some(average_price)) =
+        (row.min_price, row.max_price, row.average_price)
+    else {
+        return Ok(None);
+    };
+
+    let periods = get_bonus_periods(pool, product_id).await?;
+    let today = OffsetDateTime::now_utc().date();
+    let first_recorded = get_first_recorded_date(pool, product_id)
+        .await?
+        .unwrap_or(today);
+    Ok(Some(PriceStats {
+        min_price,
+        max_price,
+        average_price,
+        last_bonus_date: row.last_bonus_date,
+        bonus_weeks: row.bonus_weeks,
+        average_discount: row.average_discount,
+        next_bonus: prediction::predict_next_bonus(&periods, first_recorded, today),
+    }))
+}
+
+/// Returns the date of the first recorded price of a product.
+pub async fn get_first_recorded_date(
+    pool: &SqlitePool,
+    product_id: i64,
+) -> Result<Option<Date>, Error> {
+    sqlx::query_scalar!(
+        r#"SELECT DATE(MIN(created_at)) AS "date: Date" FROM products_history WHERE product_id = ?"#,
+        product_id
+    )
+    .fetch_one(pool)
+    .await
+}
+
+/// Stores a search query so result pages can refer to it by ID, as the query may
+/// not fit in the callback data of a button. Returns the ID of the search.
+pub async fn insert_search(pool: &SqlitePool, chat_id: i64, query: &str) -> Result<i64, Error> {
+    sqlx::query_file!("src/queries/insert_search.sql", chat_id, query)
+        .execute(pool)
+        .await
+        .map(|result| result.last_insert_rowid())
+}
+
+pub async fn get_search_query(
+    pool: &SqlitePool,
+    search_id: i64,
+    chat_id: i64,
+) -> Result<Option<String>, Error> {
+    sqlx::query_scalar!(
+        "SELECT query FROM searches WHERE id = ? AND chat_id = ?",
+        search_id,
+        chat_id
+    )
+    .fetch_optional(pool)
+    .await
+}
+
+/// Returns the serialized dialogue state of a chat, if the chat is in a dialogue
+pub async fn get_dialogue_state(pool: &SqlitePool, chat_id: i64) -> Result<Option<String>, Error> {
+    sqlx::query_scalar!("SELECT state FROM dialogues WHERE chat_id = ?", chat_id)
+        .fetch_optional(pool)
+        .await
+}
+
+pub async fn set_dialogue_state(
+    pool: &SqlitePool,
+    chat_id: i64,
+    state: &str,
+) -> Result<SqliteQueryResult, Error> {
+    sqlx::query_file!("src/queries/upsert_dialogue.sql", chat_id, state)
+        .execute(pool)
+        .await
+}
+
+pub async fn delete_dialogue_state(
+    pool: &SqlitePool,
+    chat_id: i64,
+) -> Result<SqliteQueryResult, Error> {
+    sqlx::query!("DELETE FROM dialogues WHERE chat_id = ?", chat_id)
+        .execute(pool)
+        .await
+}
+
+pub async fn get_chat_settings(
+    pool: &SqlitePool,
+    chat_id: i64,
+) -> Result<Option<ChatSettings>, Error> {
+    sqlx::query_as!(
+        ChatSettings,
+        r#"SELECT chat_id,
+          notification_days,
+          notification_hour,
+          send_nothing_on_sale,
+          last_day_reminder,
+          digest,
+          last_notified_at AS "last_notified_at: PrimitiveDateTime"
+        FROM chat_settings
+        WHERE chat_id = ?"#,
+        chat_id
+    )
+    .fetch_optional(pool)
+    .await
+}
+
+/// Stores the preferences of a chat, `last_notified_at` is left untouched.
+pub async fn set_chat_settings(
+    pool: &SqlitePool,
+    settings: &ChatSettings,
+) -> Result<SqliteQueryResult, Error> {
+    sqlx::query_file!(
+        "src/queries/upsert_chat_settings.sql",
+        settings.chat_id,
+        settings.notification_days,
+        settings.notification_hour,
+        settings.send_nothing_on_sale,
+        settings.last_day_reminder,
+        settings.digest
+    )
+    .execute(pool)
+    .await
+}
+
+/// Returns the settings of all active chats that track or watch something,
+/// including the default settings of chats that never changed them.
+pub async fn get_all_chat_settings(pool: &SqlitePool) -> Result<Vec<ChatSettings>, Error> {
+    sqlx::query_file_as!(ChatSettings, "src/queries/select_all_chat_settings.sql")
+        .fetch_all(pool)
+        .await
+}
+
+pub async fn set_last_notified_at(
+    pool: &SqlitePool,
+    chat_id: i64,
+    notified_at: PrimitiveDateTime,
+) -> Result<SqliteQueryResult, Error> {
+    sqlx::query_file!(
+        "src/queries/update_chat_last_notified.sql",
+        chat_id,
+        notified_at
+    )
+    .execute(pool)
+    .await
+}
+
+/// Returns when prices were last fetched, `None` if they never were.
+pub async fn get_last_price_fetch(pool: &SqlitePool) -> Result<Option<PrimitiveDateTime>, Error> {
+    sqlx::query_scalar!(
+        r#"SELECT MAX(created_at) AS "created_at: PrimitiveDateTime" FROM products_history"#
+    )
+    .fetch_one(pool)
+    .await
+}
+
+/// Marks a chat as active or inactive, inactive chats are not notified. Chats
+/// become inactive when they block the bot or are deleted.
+pub async fn set_chat_active(
+    pool: &SqlitePool,
+    chat_id: i64,
+    active: bool,
+) -> Result<SqliteQueryResult, Error> {
+    sqlx::query_file!("src/queries/update_chat_active.sql", chat_id, active)
+        .execute(pool)
+        .await
+}
+
+/// Tables with a `chat_id` column, moved to the new ID when a group becomes a
+/// supergroup
+const CHAT_TABLES: [&str; 9] = [
+    "tracked_products",
+    "watches",
+    "watch_notifications",
+    "brand_watches",
+    "taxonomy_watches",
+    "searches",
+    "dialogues",
+    "chat_settings",
+    "notifications_sent",
+];
+
+/// Moves everything of a chat to its new ID. Rows the new chat already has are
+/// kept and the ones of the old chat dropped.
+pub async fn migrate_chat(
+    pool: &SqlitePool,
+    old_chat_id: i64,
+    new_chat_id: i64,
+) -> Result<(), Error> {
+    let mut transaction = pool.begin().await?;
+    // the table names are constants, so the queries are safe
+    for table in CHAT_TABLES {
+        sqlx::query(AssertSqlSafe(format!(
+            "UPDATE OR IGNORE {} SET chat_id = ?2 WHERE chat_id = ?1",
+            table
+        )))
+        .bind(old_chat_id)
+        .bind(new_chat_id)
+        .execute(&mut *transaction)
+        .await?;
+        sqlx::query(AssertSqlSafe(format!(
+            "DELETE FROM {} WHERE chat_id = ?1",
+            table
+        )))
+        .bind(old_chat_id)
+        .execute(&mut *transaction)
+        .await?;
+    }
+    transaction.commit().await
+}
+
+/// Returns the product as it was last fetched, to compare it with the current one
+pub async fn get_product_snapshot(
+    pool: &SqlitePool,
+    product_id: i64,
+) -> Result<Option<String>, Error> {
+    sqlx::query_scalar!(
+        "SELECT snapshot FROM product_snapshots WHERE product_id = ?",
+        product_id
+    )
+    .fetch_optional(pool)
+    .await
+}
+
+pub async fn set_product_snapshot(
+    pool: &SqlitePool,
+    product_id: i64,
+    snapshot: &str,
+) -> Result<SqliteQueryResult, Error> {
+    sqlx::query_file!(
+        "src/queries/upsert_product_snapshot.sql",
+        product_id,
+        snapshot
+    )
+    .execute(pool)
+    .await
+}
+
+/// Change of a product found when fetching prices, that trackers are alerted of
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ProductChangeKind {
+    /// The regular price went up, outside of any discount
+    PriceIncrease,
+    /// The product can be ordered again
+    BackInStock,
+}
+
+impl ProductChangeKind {
+    fn as_str(self) -> &'static str {
+        match self {
+            ProductChangeKind::PriceIncrease => "price_increase",
+            ProductChangeKind::BackInStock => "back_in_stock",
+        }
+    }
+}
+
+pub async fn insert_product_change(
+    pool: &SqlitePool,
+    product_id: i64,
+    kind: ProductChangeKind,
+    old_price: i64,
+    new_price: i64,
+) -> Result<SqliteQueryResult, Error> {
+    let kind = kind.as_str();
+    sqlx::query_file!(
+        "src/queries/insert_product_change.sql",
+        product_id,
+        kind,
+        old_price,
+        new_price
+    )
+    .execute(pool)
+    .await
+}
+
+pub async fn insert_shrinkflation(
+    pool: &SqlitePool,
+    product_id: i64,
+    previous_unit_price: i64,
+    unit_price: i64,
+    previous_unit_size: &str,
+    unit_size: &str,
+) -> Result<SqliteQueryResult, Error> {
+    sqlx::query_file!(
+        "src/queries/insert_shrinkflation.sql",
+        product_id,
+        previous_unit_price,
+        unit_price,
+        previous_unit_size,
+        unit_size
+    )
+    .execute(pool)
+    .await
+}
+
+pub struct ProductChange {
+    pub product_id: i64,
+    pub kind: String,
+    pub old_price: i64,
+    pub new_price: i64,
+    pub created_at: PrimitiveDateTime,
+    pub chat_id: i64,
+    pub name: String,
+    pub url: String,
+}
+
+impl ProductChange {
+    /// Returns a markdown formatted message for the Telegram bot.
+    pub fn message(&self) -> String {
+        if self.kind == ProductChangeKind::BackInStock.as_str() {
+            format!(
+                "[{}](https://www.ah.nl{}) is back in stock: {}",
+                escape(self.name.as_str()),
+                self.url,
+                escape(format_price(self.new_price).as_str())
+            )
+        } else {
+            format!(
+                "[{}](https://www.ah.nl{}) went up in price from {} to {}",
+                escape(self.name.as_str()),
+                self.url,
+                escape(format_price(self.old_price).as_str()),
+                escape(format_price(self.new_price).as_str())
+            )
+        }
+    }
+}
+
+/// Returns the changes of tracked products found after `since`, for every chat
+/// that tracks them and didn't mute them.
+pub async fn get_product_changes(
+    pool: &SqlitePool,
+    since: PrimitiveDateTime,
+) -> Result<Vec<ProductChange>, Error> {
+    sqlx::query_file_as!(
+        ProductChange,
+        "src/queries/select_product_changes.sql",
+        since
+    )
+    .fetch_all(pool)
+    .await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn lowest_price_needs_enough_history() {
+        assert_eq!(lowest_price_period(0), None);
+        assert_eq!(lowest_price_period(1), None);
+        assert_eq!(lowest_price_period(2), None);
+        assert_eq!(lowest_price_period(3), Some("3 months".to_string()));
+        assert_eq!(lowest_price_period(11), Some("11 months".to_string()));
+        assert_eq!(lowest_price_period(12), Some("a year".to_string()));
+        assert_eq!(lowest_price_period(23), Some("a year".to_string()));
+        assert_eq!(lowest_price_period(30), Some("2 years".to_string()));
+    }
+}
@@ -0,0 +1,92 @@
+use std::{fmt, future::Future, pin::Pin, sync::Arc};
+
+use serde::{Deserialize, Serialize};
+use sqlx::SqlitePool;
+use teloxide::{
+    dispatching::dialogue::{Dialogue, Storage},
+    types::ChatId,
+};
+
+use crate::{db, target::Target};
+
+/// Step of a multi-step conversation with a chat. Chats that are not in the
+/// middle of a conversation are `Idle`.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub enum State {
+    #[default]
+    Idle,
+    /// Waiting for the user to type the target of a tracked product
+    ReceiveTarget { product_id: i64 },
+    /// Waiting for the user to confirm the target of a tracked product
+    ConfirmTarget { product_id: i64, target: Target },
+}
+
+pub type BotDialogue = Dialogue<State, SqliteDialogueStorage>;
+
+#[derive(Debug)]
+pub enum DialogueStorageError {
+    Database(sqlx::Error),
+    Serialization(serde_json::Error),
+}
+
+impl fmt::Display for DialogueStorageError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            DialogueStorageError::Database(e) => write!(f, "database error: {}", e),
+            DialogueStorageError::Serialization(e) => write!(f, "invalid dialogue state: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for DialogueStorageError {}
+
+type StorageFuture<T> = Pin<Box<dyn Future<Output = Result<T, DialogueStorageError>> + Send>>;
+
+/// Stores dialogue states in the bot database so conversations survive restarts.
+/// The SQLite storage of teloxide can't be used as it depends on another version
+/// of sqlx.
+pub struct SqliteDialogueStorage {
+    pool: SqlitePool,
+}
+
+impl SqliteDialogueStorage {
+    pub fn new(pool: SqlitePool) -> Arc<Self> {
+        Arc::new(Self { pool })
+    }
+}
+
+impl Storage<State> for SqliteDialogueStorage {
+    type Error = DialogueStorageError;
+
+    fn remove_dialogue(self: Arc<Self>, chat_id: ChatId) -> StorageFuture<()> {
+        Box::pin(async move {
+            db::delete_dialogue_state(&self.pool, chat_id.0)
+                .await
+                .map_err(DialogueStorageError::Database)?;
+            Ok(())
+        })
+    }
+
+    fn update_dialogue(self: Arc<Self>, chat_id: ChatId, dialogue: State) -> StorageFuture<()> {
+        Box::pin(async move {
+            let state =
+                serde_json::to_string(&dialogue).map_err(DialogueStorageError::Serialization)?;
+            db::set_dialogue_state(&self.pool, chat_id.0, &state)
+                .await
+                .map_err(DialogueStorageError::Database)?;
+            Ok(())
+        })
+    }
+
+    fn get_dialogue(self: Arc<Self>, chat_id: ChatId) -> StorageFuture<Option<State>> {
+        Box::pin(async move {
+            let state = db::get_dialogue_state(&self.pool, chat_id.0)
+                .await
+                .map_err(DialogueStorageError::Database)?;
+            state
+                .map(|state| serde_json::from_str(&state))
+                .transpose()
+                .map_err(DialogueStorageError::Serialization)
+        })
+    }
+}
@@ -1,4 +1,149 @@
-pub enum Error {
-    DBUniqueConstraintViolation,
-    DBError(sqlx::Error),
+use std::fmt;
+
+use teloxide::{types::ChatId, ApiError, DownloadError, RequestError};
+
+use crate::{chart::ChartError, dialogue::DialogueStorageError};
+
+/// What a failed Telegram request means for the chat it was sent to
+#[derive(Debug, PartialEq)]
+pub enum ChatFailure {
+    /// The bot can't send messages to the chat anymore, e.g. it was blocked by the
+    /// user or the chat was deleted
+    Gone,
+    /// The group became a supergroup with another ID
+    Migrated(ChatId),
+    /// Anything else, e.g. a network error, the chat may still be reachable
+    Other,
+}
+
+impl ChatFailure {
+    pub fn of(e: &RequestError) -> Self {
+        match e {
+            RequestError::MigrateToChatId(chat_id) => ChatFailure::Migrated(*chat_id),
+            RequestError::Api(
+                ApiError::BotBlocked
+                | ApiError::BotKicked
+                | ApiError::BotKickedFromSupergroup
+                | ApiError::BotKickedFromChannel
+                | ApiError::ChatNotFound
+                | ApiError::UserNotFound
+                | ApiError::UserDeactivated
+                | ApiError::GroupDeactivated
+                | ApiError::CantInitiateConversation
+                | ApiError::CantTalkWithBots,
+            ) => ChatFailure::Gone,
+            _ => ChatFailure::Other,
+        }
+    }
+}
+
+/// Errors that end the handling of a message or a button press
+#[derive(Debug)]
+pub enum HandlerError {
+    Telegram(RequestError),
+    /// A request to AH failed or returned something unexpected
+    Api(reqwest::Error),
+    Database(sqlx::Error),
+    Dialogue(DialogueStorageError),
+    Chart(ChartError),
+    /// AH no longer sells the product, or returned it without the data we need
+    ProductNotFound,
+}
+
+pub type HandlerResult = Result<(), HandlerError>;
+
+/// A [`HandlerError`] with the handler and chat of the update it ended, so the
+/// dispatcher can log where it happened
+#[derive(Debug)]
+pub struct UpdateError {
+    pub handler: &'static str,
+    /// `None` for updates without a chat, e.g. inline queries
+    pub chat_id: Option<ChatId>,
+    pub error: HandlerError,
+}
+
+pub type UpdateResult = Result<(), UpdateError>;
+
+impl HandlerError {
+    /// Reply telling the user what went wrong. Telegram errors have none as the
+    /// reply would likely fail as well.
+    pub fn user_message(&self) -> Option<&'static str> {
+        match self {
+            HandlerError::Telegram(_) => None,
+            HandlerError::Api(_) => {
+                Some("Albert Heijn can't be reached right now, try again later")
+            }
+            HandlerError::Database(_) | HandlerError::Dialogue(_) => {
+                Some("Something went wrong, try again later")
+            }
+            HandlerError::Chart(_) => Some("Failed to draw the chart, try again later"),
+            HandlerError::ProductNotFound => Some("This product is not available at AH anymore"),
+        }
+    }
+
+    pub fn in_update(self, handler: &'static str, chat_id: Option<ChatId>) -> UpdateError {
+        UpdateError {
+            handler,
+            chat_id,
+            error: self,
+        }
+    }
+}
+
+impl fmt::Display for HandlerError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            HandlerError::Telegram(e) => write!(f, "telegram request failed: {}", e),
+            HandlerError::Api(e) => write!(f, "AH request failed: {}", e),
+            HandlerError::Database(e) => write!(f, "database error: {}", e),
+            HandlerError::Dialogue(e) => write!(f, "dialogue error: {}", e),
+            HandlerError::Chart(e) => write!(f, "{}", e),
+            HandlerError::ProductNotFound => write!(f, "product not found"),
+        }
+    }
+}
+
+impl fmt::Display for UpdateError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.chat_id {
+            Some(chat_id) => write!(f, "{} in chat {}: {}", self.handler, chat_id.0, self.error),
+            None => write!(f, "{}: {}", self.handler, self.error),
+        }
+    }
+}
+
+impl From<RequestError> for HandlerError {
+    fn from(e: RequestError) -> Self {
+        HandlerError::Telegram(e)
+    }
+}
+
+impl From<DownloadError> for HandlerError {
+    fn from(e: DownloadError) -> Self {
+        HandlerError::Telegram(RequestError::from(e))
+    }
+}
+
+impl From<reqwest::Error> for HandlerError {
+    fn from(e: reqwest::Error) -> Self {
+        HandlerError::Api(e)
+    }
+}
+
+impl From<sqlx::Error> for HandlerError {
+    fn from(e: sqlx::Error) -> Self {
+        HandlerError::Database(e)
+    }
+}
+
+impl From<DialogueStorageError> for HandlerError {
+    fn from(e: DialogueStorageError) -> Self {
+        HandlerError::Dialogue(e)
+    }
+}
+
+impl From<ChartError> for HandlerError {
+    fn from(e: ChartError) -> Self {
+        HandlerError::Chart(e)
+    }
 }
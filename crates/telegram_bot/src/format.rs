@@ -0,0 +1,25 @@
+/// Formats a price stored in the database in cents, e.g. `199` becomes `€1.99`.
+pub fn format_price(cents: i64) -> String {
+    format!("€{:.2}", cents as f64 / 100.0)
+}
+
+/// Maximum length of a Telegram message
+pub const MESSAGE_LENGTH_LIMIT: usize = 4096;
+
+/// Joins `parts` with `separator` into as few messages as possible, starting a new
+/// message before a part that would make the current one exceed the length limit.
+pub fn split_message(parts: impl IntoIterator<Item = String>, separator: &str) -> Vec<String> {
+    let mut messages: Vec<String> = vec![];
+    for part in parts {
+        match messages.last_mut() {
+            Some(message)
+                if message.len() + separator.len() + part.len() <= MESSAGE_LENGTH_LIMIT =>
+            {
+                message.push_str(separator);
+                message.push_str(&part);
+            }
+            _ => messages.push(part),
+        }
+    }
+    messages
+}
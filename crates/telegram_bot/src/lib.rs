@@ -1,2 +1,10 @@
+pub mod barcode;
+pub mod callback;
+pub mod chart;
 pub mod db;
+pub mod dialogue;
 pub mod errors;
+pub mod format;
+pub mod prediction;
+pub mod settings;
+pub mod target;
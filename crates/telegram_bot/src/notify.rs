@@ -1,12 +1,27 @@
-use ah_api::client::AHClient;
+use ah_api::{client::AHClient, product::Product};
 use clap::Parser;
+use futures::{stream, StreamExt};
 use sqlx::SqlitePool;
-use std::{thread, time};
-use telegram_bot::db;
+use std::{
+    cell::RefCell,
+    collections::{BTreeMap, HashMap, HashSet},
+    future::Future,
+    time,
+};
+use telegram_bot::{
+    callback::mute_buttons,
+    db::{self, NotificationKind, ProductChangeKind},
+    errors::ChatFailure,
+    format::MESSAGE_LENGTH_LIMIT,
+    settings::{to_dutch_time, ChatSettings},
+};
+use teloxide::utils::markdown::escape;
 use teloxide::{
-    adaptors::throttle::Limits,
+    adaptors::{throttle::Limits, Throttle},
     prelude::*,
-    types::{InputFile, ParseMode},
+    sugar::request::RequestLinkPreviewExt,
+    types::{InlineKeyboardMarkup, InputFile, ParseMode},
+    RequestError,
 };
 
 #[derive(Parser, Debug)]
@@ -18,6 +33,80 @@ pub struct Cli {
     pub dry_run: bool,
     #[arg(long = "no-fetch")]
     pub no_fetch: bool,
+    /// Only fetch prices again when they are older than this many hours
+    #[arg(long = "max-price-age", default_value_t = 12)]
+    pub max_price_age: i64,
+    /// Number of products fetched at the same time
+    #[arg(long = "fetch-concurrency", default_value_t = 4)]
+    pub fetch_concurrency: usize,
+    /// Milliseconds to wait before every request to AH, to be respectful to the API
+    #[arg(long = "fetch-delay-ms", default_value_t = 1000)]
+    pub fetch_delay_ms: u64,
+    /// Keep the price history of products nobody tracks for this many days, in
+    /// case they are tracked again
+    #[arg(long = "product-retention-days", default_value_t = 365)]
+    pub product_retention_days: i64,
+}
+
+/// Chats to notify in this run, by chat ID
+type DueChats = HashMap<i64, ChatSettings>;
+
+/// Chats that failed while sending in this run: groups that became a supergroup
+/// map to their new ID and chats that are gone to `None`. Messages of the rest of
+/// the run follow them.
+#[derive(Default)]
+struct ChatRoutes(RefCell<HashMap<i64, Option<i64>>>);
+
+impl ChatRoutes {
+    /// Returns the ID to send messages of a chat to, or `None` if it is gone
+    fn resolve(&self, chat_id: i64) -> Option<i64> {
+        match self.0.borrow().get(&chat_id) {
+            Some(new_chat_id) => *new_chat_id,
+            None => Some(chat_id),
+        }
+    }
+
+    /// Returns the ID a chat had at the start of the run, as the due chats are
+    /// keyed by it while queries return its new ID after a migration
+    fn original(&self, chat_id: i64) -> i64 {
+        self.0
+            .borrow()
+            .iter()
+            .find(|(_, new_chat_id)| **new_chat_id == Some(chat_id))
+            .map_or(chat_id, |(original, _)| *original)
+    }
+
+    fn record(&self, chat_id: i64, new_chat_id: Option<i64>) {
+        self.0.borrow_mut().insert(chat_id, new_chat_id);
+    }
+}
+
+/// Returns since when a chat should be notified of changes, chats that were
+/// never notified get the changes of the last week.
+fn notified_since(
+    settings: &ChatSettings,
+    now: ::time::PrimitiveDateTime,
+) -> ::time::PrimitiveDateTime {
+    settings
+        .last_notified_at
+        .unwrap_or(now - ::time::Duration::weeks(1))
+}
+
+/// Returns whether a change recorded at `created_at` is new to a chat. Every run
+/// covers the changes since the chat was last notified up to, but not including,
+/// `until`, and stores `until` as the new notification time, so no change falls
+/// in two runs.
+fn is_unsent(
+    settings: &ChatSettings,
+    created_at: ::time::PrimitiveDateTime,
+    until: ::time::PrimitiveDateTime,
+) -> bool {
+    created_at >= notified_since(settings, until) && created_at < until
+}
+
+fn utc_now() -> ::time::PrimitiveDateTime {
+    let now = ::time::OffsetDateTime::now_utc();
+    ::time::PrimitiveDateTime::new(now.date(), now.time())
 }
 
 #[tokio::main]
@@ -35,109 +124,969 @@ async fn main() {
         .await
         .expect("Migrations failed");
 
-    if !args.no_fetch {
+    let now = utc_now();
+
+    // the notifier runs every hour, but every chat is only notified at its own
+    // days and time
+    let chat_settings = db::get_all_chat_settings(&pool)
+        .await
+        .expect("Failed to get chat settings");
+    let reminder_chats = chat_settings
+        .iter()
+        .filter(|settings| settings.is_reminder_due(now))
+        .map(|settings| (settings.chat_id, settings.clone()))
+        .collect::<DueChats>();
+    let due_chats = chat_settings
+        .into_iter()
+        .filter(|settings| settings.is_due(now))
+        .map(|settings| (settings.chat_id, settings))
+        .collect::<DueChats>();
+    if due_chats.is_empty() && reminder_chats.is_empty() {
+        log::info!("No chats to notify");
+        return;
+    }
+
+    if !args.dry_run {
+        let deleted = db::delete_untracked_products(&pool, args.product_retention_days)
+            .await
+            .expect("Failed to delete untracked products");
+        if deleted > 0 {
+            log::info!("Deleted {} products nobody tracks", deleted);
+        }
+    }
+
+    let fetch_delay = time::Duration::from_millis(args.fetch_delay_ms);
+    let ah_client = if args.no_fetch {
+        None
+    } else {
         let ah_client = AHClient::new()
             .await
             .expect("Failed to initalize AH client");
 
-        get_current_prices(&pool, ah_client)
+        let last_fetch = db::get_last_price_fetch(&pool)
             .await
-            .expect("Failed to get current prices");
-    }
+            .expect("Failed to get last price fetch");
+        match last_fetch {
+            Some(fetched_at) if now - fetched_at < ::time::Duration::hours(args.max_price_age) => {
+                log::info!("Prices were fetched at {}, not fetching again", fetched_at);
+            }
+            _ => {
+                get_current_prices(&pool, &ah_client, args.fetch_concurrency, fetch_delay)
+                    .await
+                    .expect("Failed to get current prices");
+            }
+        }
+        Some(ah_client)
+    };
 
-    notify_users_of_discounts(&pool, args.dry_run)
+    // changes recorded while fetching prices are sent in this run, so the window
+    // of changes ends after the fetch
+    let notified_until = utc_now();
+
+    let routes = ChatRoutes::default();
+    notify_users_of_discounts(&pool, &routes, &due_chats, args.dry_run)
         .await
         .expect("Failed to notify users of discounts");
+
+    notify_users_of_last_days(&pool, &routes, &reminder_chats, now, args.dry_run)
+        .await
+        .expect("Failed to remind users of discounts ending today");
+
+    notify_users_of_price_drops(&pool, &routes, &due_chats, notified_until, args.dry_run)
+        .await
+        .expect("Failed to notify users of price drops");
+
+    notify_users_of_shrinkflation(&pool, &routes, &due_chats, notified_until, args.dry_run)
+        .await
+        .expect("Failed to notify users of shrinkflation");
+
+    notify_users_of_product_changes(&pool, &routes, &due_chats, notified_until, args.dry_run)
+        .await
+        .expect("Failed to notify users of product changes");
+
+    if let Some(ah_client) = ah_client {
+        notify_users_of_watches(
+            &pool,
+            &routes,
+            &ah_client,
+            &due_chats,
+            fetch_delay,
+            args.dry_run,
+        )
+        .await
+        .expect("Failed to notify users of watches");
+    }
+
+    if !args.dry_run {
+        // chats that are gone are left alone, so their settings are not created
+        // again, and migrated chats are stored at their new ID
+        for chat_id in due_chats
+            .keys()
+            .filter_map(|chat_id| routes.resolve(*chat_id))
+        {
+            db::set_last_notified_at(&pool, chat_id, notified_until)
+                .await
+                .expect("Failed to store notification time");
+        }
+    }
 }
 
-async fn get_current_prices(pool: &SqlitePool, ah_client: AHClient) -> Result<(), sqlx::Error> {
-    log::info!("Fetching current prices");
+/// Statistics of a price refresh
+#[derive(Debug, Default)]
+struct RefreshStats {
+    /// Products whose price was fetched and stored
+    fetched: usize,
+    /// Products that could not be fetched, e.g. because AH doesn't sell them anymore
+    failed: usize,
+    /// Fetched products whose price or discount changed since the last refresh
+    changed: usize,
+}
 
-    let product_ids = db::get_all_product_ids(pool).await?;
-    let one_sec = time::Duration::from_secs(1);
-    for product_id in product_ids {
-        log::info!("Getting product with ID: {}", product_id);
+/// Compares `product` with the snapshot of the previous fetch and records the
+/// changes trackers are alerted of, including shrinkflation. A bonus ending is not
+/// a price increase, so price increases are only recorded when neither fetch had a
+/// discount.
+async fn record_product_changes(
+    pool: &SqlitePool,
+    product_id: i64,
+    product: &Product,
+) -> Result<(), sqlx::Error> {
+    let previous = db::get_product_snapshot(pool, product_id)
+        .await?
+        .and_then(|snapshot| serde_json::from_str::<Product>(&snapshot).ok());
+    if let Some(previous) = previous {
+        let diff = previous.diff(product);
+        let old_price = i64::from(previous.get_price_for_db());
+        let new_price = i64::from(product.get_price_for_db());
+        if diff.is_back_in_stock() {
+            db::insert_product_change(
+                pool,
+                product_id,
+                ProductChangeKind::BackInStock,
+                old_price,
+                new_price,
+            )
+            .await?;
+        }
+        if diff.is_shrinkflation() {
+            // both snapshots have a price per unit when it went up
+            let previous_unit_price = previous.get_unit_price_for_db().unwrap_or_default();
+            let unit_price = product.get_unit_price_for_db().unwrap_or_default();
+            db::insert_shrinkflation(
+                pool,
+                product_id,
+                i64::from(previous_unit_price),
+                i64::from(unit_price),
+                &previous.price.unit_size,
+                &product.price.unit_size,
+            )
+            .await?;
+        }
+        if diff.price_increased() && !previous.is_on_discount() && !product.is_on_discount() {
+            db::insert_product_change(
+                pool,
+                product_id,
+                ProductChangeKind::PriceIncrease,
+                old_price,
+                new_price,
+            )
+            .await?;
+        }
+    }
 
-        // be respectful to the API
-        thread::sleep(one_sec);
+    match serde_json::to_string(product) {
+        Ok(snapshot) => {
+            db::set_product_snapshot(pool, product_id, &snapshot).await?;
+        }
+        Err(err) => log::error!("Failed to serialize product {}: {}", product_id, err),
+    }
+    Ok(())
+}
 
-        let product = ah_client.get_product(product_id.to_string().as_str()).await;
-        match product {
-            Ok(resp) => {
-                db::insert_product_history(
-                    pool,
-                    &resp
-                        .card
-                        .products
-                        .first()
-                        .expect("There should be a product"),
-                )
-                .await?;
-            }
+/// Log the progress of a price refresh after this many products
+const PROGRESS_INTERVAL: usize = 100;
+
+/// Fetches the current prices of all tracked products, `concurrency` at a time.
+/// Every request waits `delay` first, to be respectful to the API.
+async fn get_current_prices(
+    pool: &SqlitePool,
+    ah_client: &AHClient,
+    concurrency: usize,
+    delay: time::Duration,
+) -> Result<RefreshStats, sqlx::Error> {
+    let product_ids = db::get_product_ids_to_fetch(pool).await?;
+    let total = product_ids.len();
+    log::info!(
+        "Fetching current prices of {} products, {} at a time",
+        total,
+        concurrency
+    );
+
+    let started_at = time::Instant::now();
+    let mut responses = stream::iter(product_ids)
+        .map(|product_id| async move {
+            tokio::time::sleep(delay).await;
+            let response = ah_client.get_product(&product_id.to_string()).await;
+            (product_id, response)
+        })
+        .buffer_unordered(concurrency.max(1));
+
+    // the database is only written from here, so SQLite is never written to
+    // concurrently
+    let mut stats = RefreshStats::default();
+    while let Some((product_id, response)) = responses.next().await {
+        let product = match response {
+            Ok(response) => response.card.products.into_iter().next(),
             Err(err) => {
                 log::error!("Failed to get product with id {}: {}", product_id, err);
-                continue;
+                None
+            }
+        };
+        match product {
+            Some(product) => {
+                let previous = db::get_latest_price(pool, product_id).await?;
+                let changed = previous.is_some_and(|previous| {
+                    previous.price != i64::from(product.get_price_for_db())
+                        || previous.discount.as_ref() != product.get_discount_text()
+                });
+                db::insert_product_history(pool, &product).await?;
+                record_product_changes(pool, product_id, &product).await?;
+                stats.fetched += 1;
+                if changed {
+                    stats.changed += 1;
+                }
             }
+            None => stats.failed += 1,
+        }
+
+        let done = stats.fetched + stats.failed;
+        if done % PROGRESS_INTERVAL == 0 {
+            log::info!("Fetched {}/{} products", done, total);
+        }
+    }
+
+    log::info!(
+        "Fetched prices in {:.1?}: {} fetched, {} failed, {} changed",
+        started_at.elapsed(),
+        stats.fetched,
+        stats.failed,
+        stats.changed
+    );
+    Ok(stats)
+}
+
+/// Handles a message that could not be sent. Chats that blocked the bot or were
+/// deleted are marked inactive, so they are not notified anymore, and groups that
+/// became a supergroup are moved to their new ID.
+async fn handle_send_error(
+    pool: &SqlitePool,
+    routes: &ChatRoutes,
+    chat_id: i64,
+    e: &RequestError,
+) -> Result<(), sqlx::Error> {
+    match ChatFailure::of(e) {
+        ChatFailure::Gone => {
+            log::warn!("Chat {} is gone, marking it inactive: {}", chat_id, e);
+            db::set_chat_active(pool, chat_id, false).await?;
+            routes.record(chat_id, None);
+        }
+        ChatFailure::Migrated(new_chat_id) => {
+            log::warn!("Chat {} migrated to {}", chat_id, new_chat_id.0);
+            db::migrate_chat(pool, chat_id, new_chat_id.0).await?;
+            routes.record(chat_id, Some(new_chat_id.0));
+        }
+        ChatFailure::Other => {
+            log::error!("Failed to send message to {}. Error: {}", chat_id, e);
         }
     }
     Ok(())
 }
 
-async fn notify_users_of_discounts(pool: &SqlitePool, dry_run: bool) -> Result<(), sqlx::Error> {
+/// Sends a message to a chat with `send`, at its new ID if it migrated. When it
+/// migrates while sending, the message is sent again to the new ID. Returns the ID
+/// the message was delivered to, or `None` if sending failed.
+async fn send_to_chat<F, Fut>(
+    pool: &SqlitePool,
+    routes: &ChatRoutes,
+    chat_id: i64,
+    send: F,
+) -> Result<Option<i64>, sqlx::Error>
+where
+    F: Fn(ChatId) -> Fut,
+    Fut: Future<Output = Result<(), RequestError>>,
+{
+    let Some(mut chat_id) = routes.resolve(chat_id) else {
+        return Ok(None);
+    };
+    loop {
+        let Err(e) = send(ChatId(chat_id)).await else {
+            return Ok(Some(chat_id));
+        };
+        handle_send_error(pool, routes, chat_id, &e).await?;
+        match routes.resolve(chat_id) {
+            Some(new_chat_id) if new_chat_id != chat_id => chat_id = new_chat_id,
+            _ => return Ok(None),
+        }
+    }
+}
+
+/// Keyboard of notifications of tracked products
+fn create_mute_keyboard(product_id: i64) -> InlineKeyboardMarkup {
+    InlineKeyboardMarkup::new(vec![mute_buttons(product_id, false)])
+}
+
+async fn notify_users_of_discounts(
+    pool: &SqlitePool,
+    routes: &ChatRoutes,
+    due_chats: &DueChats,
+    dry_run: bool,
+) -> Result<(), sqlx::Error> {
     log::info!("Notifying users of discounts");
 
     let bot = Bot::from_env().throttle(Limits::default());
 
-    let to_notify = db::get_discounted_products(pool).await?;
+    // products can have several history rows with the same discount
+    let mut discounts = HashSet::new();
+    let to_notify = db::get_discounted_products(pool)
+        .await?
+        .into_iter()
+        .filter(|notification| due_chats.contains_key(&routes.original(notification.chat_id)))
+        .filter(|notification| {
+            discounts.insert((
+                notification.chat_id,
+                notification.product_id,
+                notification.discount_start_date,
+                notification.discount_end_date,
+            ))
+        });
 
+    let mut digests = BTreeMap::<i64, Vec<db::NotificationDiscount>>::new();
     for notification in to_notify {
+        if due_chats[&routes.original(notification.chat_id)].digest {
+            digests
+                .entry(notification.chat_id)
+                .or_default()
+                .push(notification);
+            continue;
+        }
+
+        send_discount_notification(
+            &bot,
+            pool,
+            routes,
+            &notification,
+            NotificationKind::Bonus,
+            notification.message(),
+            dry_run,
+        )
+        .await?;
+    }
+
+    for (chat_id, notifications) in digests {
+        send_digest(&bot, pool, routes, chat_id, notifications, dry_run).await?;
+    }
+
+    let users_not_notified = db::get_users_not_notified(pool)
+        .await?
+        .into_iter()
+        .filter(|user| {
+            due_chats
+                .get(&routes.original(*user))
+                .is_some_and(|settings| settings.send_nothing_on_sale)
+        });
+    let bot = &bot;
+    for user in users_not_notified {
+        let msg = "None of the products you are tracking are on sale this week";
+        if dry_run {
+            log::info!("Would have sent message to {}. Message: {}", user, msg);
+            continue;
+        }
+        send_to_chat(pool, routes, user, |chat_id| async move {
+            bot.send_message(chat_id, msg)
+                .parse_mode(ParseMode::MarkdownV2)
+                .await
+                .map(|_| ())
+        })
+        .await?;
+    }
+
+    Ok(())
+}
+
+/// Reminds chats that asked for it of discounts ending today, at their
+/// notification time.
+async fn notify_users_of_last_days(
+    pool: &SqlitePool,
+    routes: &ChatRoutes,
+    reminder_chats: &DueChats,
+    now: ::time::PrimitiveDateTime,
+    dry_run: bool,
+) -> Result<(), sqlx::Error> {
+    log::info!("Reminding users of discounts ending today");
+
+    let bot = Bot::from_env().throttle(Limits::default());
+
+    let today = to_dutch_time(now).date();
+    // products can have several history rows with the same discount
+    let mut discounts = HashSet::new();
+    let to_remind = db::get_last_day_discounts(pool, today)
+        .await?
+        .into_iter()
+        .filter(|notification| reminder_chats.contains_key(&routes.original(notification.chat_id)))
+        .filter(|notification| {
+            discounts.insert((
+                notification.chat_id,
+                notification.product_id,
+                notification.discount_start_date,
+                notification.discount_end_date,
+            ))
+        });
+
+    for notification in to_remind {
+        send_discount_notification(
+            &bot,
+            pool,
+            routes,
+            &notification,
+            NotificationKind::LastDay,
+            notification.last_day_message(),
+            dry_run,
+        )
+        .await?;
+    }
+
+    Ok(())
+}
+
+/// Sends a notification of a discount of a tracked product and records it was
+/// sent, so it is sent again next time when sending fails.
+async fn send_discount_notification(
+    bot: &Throttle<Bot>,
+    pool: &SqlitePool,
+    routes: &ChatRoutes,
+    notification: &db::NotificationDiscount,
+    kind: NotificationKind,
+    message: String,
+    dry_run: bool,
+) -> Result<(), sqlx::Error> {
+    if dry_run {
+        log::info!(
+            "Would have sent message to {}. Message: {}",
+            notification.chat_id,
+            message
+        );
+        return Ok(());
+    }
+
+    let message = &message;
+    let sent_to = send_to_chat(pool, routes, notification.chat_id, |chat_id| async move {
+        send_product_message(
+            bot,
+            chat_id,
+            notification.product_id,
+            &notification.image_url,
+            message.clone(),
+        )
+        .await
+    })
+    .await?;
+    if let Some(chat_id) = sent_to {
+        record_notification_sent(pool, chat_id, notification, kind).await?;
+    }
+
+    Ok(())
+}
+
+/// Records that a chat was sent a notification of a discount, the migration of
+/// a chat may have moved the record already.
+async fn record_notification_sent(
+    pool: &SqlitePool,
+    chat_id: i64,
+    notification: &db::NotificationDiscount,
+    kind: NotificationKind,
+) -> Result<(), sqlx::Error> {
+    match db::insert_notification_sent(pool, chat_id, notification, kind).await {
+        Ok(_) => Ok(()),
+        Err(sqlx::Error::Database(e)) if e.is_unique_violation() => Ok(()),
+        Err(e) => Err(e),
+    }
+}
+
+/// Sends a markdown message about a product with its photo and the buttons to
+/// mute it.
+async fn send_product_message(
+    bot: &Throttle<Bot>,
+    chat_id: ChatId,
+    product_id: i64,
+    image_url: &str,
+    message: String,
+) -> Result<(), RequestError> {
+    let keyboard = create_mute_keyboard(product_id);
+    match url::Url::parse(image_url) {
+        Ok(image_url) => bot
+            .send_photo(chat_id, InputFile::url(image_url))
+            .caption(message)
+            .parse_mode(ParseMode::MarkdownV2)
+            .reply_markup(keyboard)
+            .await
+            .map(|_| ()),
+        // products without an image are stored with an empty image url
+        Err(_) => bot
+            .send_message(chat_id, message)
+            .parse_mode(ParseMode::MarkdownV2)
+            .reply_markup(keyboard)
+            .await
+            .map(|_| ()),
+    }
+}
+
+/// Sends all discounts a chat was not notified of yet in one message, the largest
+/// savings first.
+async fn send_digest(
+    bot: &Throttle<Bot>,
+    pool: &SqlitePool,
+    routes: &ChatRoutes,
+    chat_id: i64,
+    mut to_send: Vec<db::NotificationDiscount>,
+    dry_run: bool,
+) -> Result<(), sqlx::Error> {
+    if to_send.is_empty() {
+        return Ok(());
+    }
+    to_send.sort_by_key(|notification| std::cmp::Reverse(notification.savings()));
+
+    // every message is sent with the discounts it lists, to record them once it
+    // is delivered
+    let mut messages = vec![("*Your tracked products on bonus:*".to_string(), vec![])];
+    for notification in &to_send {
+        let line = notification.digest_line();
+        let (message, notifications) = messages.last_mut().expect("there is a message");
+        if message.len() + line.len() + 1 > MESSAGE_LENGTH_LIMIT {
+            messages.push((line, vec![notification]));
+        } else {
+            message.push('\n');
+            message.push_str(&line);
+            notifications.push(notification);
+        }
+    }
+
+    for (message, notifications) in messages {
         if dry_run {
             log::info!(
                 "Would have sent message to {}. Message: {}",
-                notification.chat_id,
-                notification.message()
+                chat_id,
+                message
             );
             continue;
         }
 
-        let message = bot
-            .send_photo(
-                ChatId {
-                    0: notification.chat_id,
-                },
-                InputFile::url(url::Url::parse(&notification.image_url).unwrap()),
-            )
-            .caption(notification.message())
-            .parse_mode(ParseMode::MarkdownV2)
-            .await;
-        if message.is_err() {
-            log::error!(
-                "Failed to send message to {}. Error: {}",
-                notification.chat_id,
-                message.err().unwrap().to_string()
+        let message = &message;
+        let sent_to = send_to_chat(pool, routes, chat_id, |chat_id| async move {
+            bot.send_message(chat_id, message.clone())
+                .parse_mode(ParseMode::MarkdownV2)
+                .disable_link_preview(true)
+                .await
+                .map(|_| ())
+        })
+        .await?;
+        // the rest is sent next time, when the chat may be reachable again
+        let Some(sent_to) = sent_to else {
+            return Ok(());
+        };
+        for notification in notifications {
+            record_notification_sent(pool, sent_to, notification, NotificationKind::Bonus).await?;
+        }
+    }
+
+    Ok(())
+}
+
+/// Notifies trackers of products whose regular price dropped since they were
+/// last notified.
+async fn notify_users_of_price_drops(
+    pool: &SqlitePool,
+    routes: &ChatRoutes,
+    due_chats: &DueChats,
+    until: ::time::PrimitiveDateTime,
+    dry_run: bool,
+) -> Result<(), sqlx::Error> {
+    log::info!("Notifying users of price drops");
+
+    let bot = Bot::from_env().throttle(Limits::default());
+
+    let Some(since) = due_chats
+        .values()
+        .map(|settings| notified_since(settings, until))
+        .min()
+    else {
+        return Ok(());
+    };
+    let price_drops = db::get_price_drops(pool, since)
+        .await?
+        .into_iter()
+        .filter(|price_drop| {
+            due_chats
+                .get(&routes.original(price_drop.chat_id))
+                .is_some_and(|settings| is_unsent(settings, price_drop.recorded_at, until))
+        });
+    for price_drop in price_drops {
+        if dry_run {
+            log::info!(
+                "Would have sent message to {}. Message: {}",
+                price_drop.chat_id,
+                price_drop.message()
             );
+            continue;
         }
+
+        let price_drop = &price_drop;
+        let bot = &bot;
+        send_to_chat(pool, routes, price_drop.chat_id, |chat_id| async move {
+            send_product_message(
+                bot,
+                chat_id,
+                price_drop.product_id,
+                &price_drop.image_url,
+                price_drop.message(),
+            )
+            .await
+        })
+        .await?;
     }
 
-    let users_not_notified = db::get_users_not_notified(pool).await?;
-    for user in users_not_notified {
-        let msg = "None of the products you are tracking are on sale this week";
+    Ok(())
+}
+
+/// Notifies trackers of products that went up in price or are back in stock
+/// since they were last notified.
+async fn notify_users_of_product_changes(
+    pool: &SqlitePool,
+    routes: &ChatRoutes,
+    due_chats: &DueChats,
+    until: ::time::PrimitiveDateTime,
+    dry_run: bool,
+) -> Result<(), sqlx::Error> {
+    log::info!("Notifying users of price increases and products back in stock");
+
+    let bot = Bot::from_env().throttle(Limits::default());
+
+    let Some(since) = due_chats
+        .values()
+        .map(|settings| notified_since(settings, until))
+        .min()
+    else {
+        return Ok(());
+    };
+    let changes = db::get_product_changes(pool, since)
+        .await?
+        .into_iter()
+        .filter(|change| {
+            due_chats
+                .get(&routes.original(change.chat_id))
+                .is_some_and(|settings| is_unsent(settings, change.created_at, until))
+        });
+    for change in changes {
         if dry_run {
-            log::info!("Would have sent message to {}. Message: {}", user, msg);
+            log::info!(
+                "Would have sent message to {}. Message: {}",
+                change.chat_id,
+                change.message()
+            );
             continue;
         }
-        let message = bot
-            .send_message(ChatId { 0: user }, msg)
-            .parse_mode(ParseMode::MarkdownV2)
+
+        let change = &change;
+        let bot = &bot;
+        send_to_chat(pool, routes, change.chat_id, |chat_id| async move {
+            bot.send_message(chat_id, change.message())
+                .parse_mode(ParseMode::MarkdownV2)
+                .reply_markup(create_mute_keyboard(change.product_id))
+                .disable_link_preview(true)
+                .await
+                .map(|_| ())
+        })
+        .await?;
+    }
+
+    Ok(())
+}
+
+/// Notifies trackers of products that got smaller, but not cheaper, since they
+/// were last notified.
+async fn notify_users_of_shrinkflation(
+    pool: &SqlitePool,
+    routes: &ChatRoutes,
+    due_chats: &DueChats,
+    until: ::time::PrimitiveDateTime,
+    dry_run: bool,
+) -> Result<(), sqlx::Error> {
+    log::info!("Notifying users of shrinkflation");
+
+    let bot = Bot::from_env().throttle(Limits::default());
+
+    let Some(since) = due_chats
+        .values()
+        .map(|settings| notified_since(settings, until))
+        .min()
+    else {
+        return Ok(());
+    };
+    let shrunk_products = db::get_shrinkflation(pool, since).await?;
+    for shrunk in shrunk_products {
+        let trackers = db::get_product_trackers(pool, shrunk.product_id)
+            .await?
+            .into_iter()
+            .filter(|chat_id| {
+                due_chats
+                    .get(&routes.original(*chat_id))
+                    .is_some_and(|settings| is_unsent(settings, shrunk.detected_at, until))
+            });
+        for chat_id in trackers {
+            if dry_run {
+                log::info!(
+                    "Would have sent message to {}. Message: {}",
+                    chat_id,
+                    shrunk.message()
+                );
+                continue;
+            }
+
+            let shrunk = &shrunk;
+            let bot = &bot;
+            send_to_chat(pool, routes, chat_id, |chat_id| async move {
+                bot.send_message(chat_id, shrunk.message())
+                    .parse_mode(ParseMode::MarkdownV2)
+                    .reply_markup(create_mute_keyboard(shrunk.product_id))
+                    .await
+                    .map(|_| ())
+            })
+            .await?;
+        }
+    }
+
+    Ok(())
+}
+
+/// Maximum number of bonus products fetched for every watch
+const WATCH_SEARCH_LIMIT: usize = 30;
+
+/// Runs the saved searches, brand and category watches of all users and notifies
+/// them of matching products on discount. Every discount is only sent once per chat.
+async fn notify_users_of_watches(
+    pool: &SqlitePool,
+    routes: &ChatRoutes,
+    ah_client: &AHClient,
+    due_chats: &DueChats,
+    delay: time::Duration,
+    dry_run: bool,
+) -> Result<(), sqlx::Error> {
+    log::info!("Notifying users of watches");
+
+    let bot = Bot::from_env().throttle(Limits::default());
+    let is_due = |chat_id: &i64| due_chats.contains_key(&routes.original(*chat_id));
+
+    let watches = db::get_all_watches(pool).await?;
+    for watch in watches.into_iter().filter(|watch| is_due(&watch.chat_id)) {
+        // be respectful to the API
+        tokio::time::sleep(delay).await;
+
+        let products = ah_client
+            .search_bonus_products(&watch.query, WATCH_SEARCH_LIMIT)
             .await;
-        if message.is_err() {
-            log::error!(
-                "Failed to send message to {}. Error: {}",
-                user,
-                message.err().unwrap().to_string()
+        let label = format!("watch \"{}\"", watch.query);
+        send_watch_matches(&bot, pool, routes, watch.chat_id, &label, products, dry_run).await?;
+    }
+
+    let brand_watches = db::get_all_brand_watches(pool).await?;
+    for watch in brand_watches
+        .into_iter()
+        .filter(|watch| is_due(&watch.chat_id))
+    {
+        tokio::time::sleep(delay).await;
+
+        let products = ah_client
+            .search_bonus_products_by_brand(&watch.brand, WATCH_SEARCH_LIMIT)
+            .await;
+        let label = format!("brand {}", watch.brand);
+        send_watch_matches(&bot, pool, routes, watch.chat_id, &label, products, dry_run).await?;
+    }
+
+    let taxonomy_watches = db::get_all_taxonomy_watches(pool).await?;
+    for watch in taxonomy_watches
+        .into_iter()
+        .filter(|watch| is_due(&watch.chat_id))
+    {
+        tokio::time::sleep(delay).await;
+
+        let products = ah_client
+            .search_bonus_products_in_taxonomy(watch.taxonomy_id, WATCH_SEARCH_LIMIT)
+            .await;
+        let label = format!("category {}", watch.taxonomy_name);
+        send_watch_matches(&bot, pool, routes, watch.chat_id, &label, products, dry_run).await?;
+    }
+
+    Ok(())
+}
+
+/// Sends the discounted products found for one of the watches of a chat, skipping
+/// the ones the chat was already notified of.
+async fn send_watch_matches<E: std::fmt::Display>(
+    bot: &Throttle<Bot>,
+    pool: &SqlitePool,
+    routes: &ChatRoutes,
+    chat_id: i64,
+    label: &str,
+    products: Result<Vec<Product>, E>,
+    dry_run: bool,
+) -> Result<(), sqlx::Error> {
+    let products = match products {
+        Ok(products) => products,
+        Err(err) => {
+            log::error!("Failed to search for {} of {}: {}", label, chat_id, err);
+            return Ok(());
+        }
+    };
+    // watch notifications are recorded at the ID the chat has now
+    let Some(chat_id) = routes.resolve(chat_id) else {
+        return Ok(());
+    };
+
+    for product in products {
+        let Some(discount) = &product.discount else {
+            continue;
+        };
+        let message = format!(
+            "[{}](https://www.ah.nl{}) is on discount \\({}\\): {}",
+            escape(product.title.as_str()),
+            product.link,
+            escape(label),
+            escape(product.get_discount_text().map_or("", |text| text.as_str()))
+        );
+        if dry_run {
+            log::info!(
+                "Would have sent message to {}. Message: {}",
+                chat_id,
+                message
             );
+            continue;
+        }
+
+        // several watches of a chat can match the same product, the match is
+        // recorded before sending so it is only sent once
+        let insert =
+            db::insert_watch_notification(pool, chat_id, product.id, &discount.end_date).await;
+        match insert {
+            Ok(_) => {}
+            Err(sqlx::Error::Database(e)) if e.is_unique_violation() => continue,
+            Err(e) => return Err(e),
+        }
+
+        let message = &message;
+        let image_url = product.images.last().map(|image| &image.url);
+        let sent_to = send_to_chat(pool, routes, chat_id, |chat_id| async move {
+            match image_url {
+                Some(image_url) => bot
+                    .send_photo(chat_id, InputFile::url(image_url.clone()))
+                    .caption(message.clone())
+                    .parse_mode(ParseMode::MarkdownV2)
+                    .await
+                    .map(|_| ()),
+                None => bot
+                    .send_message(chat_id, message.clone())
+                    .parse_mode(ParseMode::MarkdownV2)
+                    .await
+                    .map(|_| ()),
+            }
+        })
+        .await?;
+        if sent_to.is_none() {
+            // try again next time, at the ID the chat may have migrated to
+            let recorded_for = routes.resolve(chat_id).unwrap_or(chat_id);
+            db::delete_watch_notification(pool, recorded_for, product.id, &discount.end_date)
+                .await?;
         }
     }
 
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ::time::macros::datetime;
+
+    fn settings(last_notified_at: Option<::time::PrimitiveDateTime>) -> ChatSettings {
+        ChatSettings {
+            chat_id: 1,
+            notification_days: 0b1111111,
+            notification_hour: 9,
+            send_nothing_on_sale: false,
+            last_day_reminder: false,
+            digest: false,
+            last_notified_at,
+        }
+    }
+
+    fn unsent(
+        settings: &ChatSettings,
+        recorded: &[::time::PrimitiveDateTime],
+        until: ::time::PrimitiveDateTime,
+    ) -> Vec<::time::PrimitiveDateTime> {
+        recorded
+            .iter()
+            .copied()
+            .filter(|created_at| is_unsent(settings, *created_at, until))
+            .collect()
+    }
+
+    #[test]
+    fn changes_are_sent_in_one_run_only() {
+        let recorded = [
+            datetime!(2026-10-18 12:00),
+            // written by the price fetch of the first run
+            datetime!(2026-10-19 07:00:05),
+            // written after the first run read its end time
+            datetime!(2026-10-19 07:10),
+        ];
+        let chat = settings(Some(datetime!(2026-10-18 07:00:30)));
+
+        let first_until = datetime!(2026-10-19 07:05:30);
+        assert_eq!(
+            unsent(&chat, &recorded, first_until),
+            [recorded[0], recorded[1]]
+        );
+
+        let chat = settings(Some(first_until));
+        let second_until = datetime!(2026-10-20 07:05:30);
+        assert_eq!(unsent(&chat, &recorded, second_until), [recorded[2]]);
+    }
+
+    #[test]
+    fn a_change_at_the_end_of_a_run_is_sent_in_the_next_one() {
+        let until = datetime!(2026-10-19 07:05:30);
+        let recorded = [until];
+
+        assert!(unsent(
+            &settings(Some(datetime!(2026-10-18 07:00))),
+            &recorded,
+            until
+        )
+        .is_empty());
+        assert_eq!(
+            unsent(
+                &settings(Some(until)),
+                &recorded,
+                datetime!(2026-10-20 07:00)
+            ),
+            recorded
+        );
+    }
+
+    #[test]
+    fn new_chats_get_the_changes_of_the_last_week() {
+        let until = datetime!(2026-10-19 07:00);
+        let recorded = [datetime!(2026-10-11 07:00), datetime!(2026-10-13 07:00)];
+
+        assert_eq!(unsent(&settings(None), &recorded, until), [recorded[1]]);
+    }
+}
@@ -0,0 +1,210 @@
+use time::{Date, Duration, Weekday};
+
+use crate::db::BonusPeriod;
+
+/// Number of upcoming weeks shown by the `/predict` command
+pub const PREDICTED_WEEKS: usize = 4;
+
+/// Number of upcoming weeks searched for the next bonus of a product
+const NEXT_BONUS_WEEKS: usize = 26;
+
+/// Weight of the overall bonus rate when estimating the probability for a week,
+/// it keeps predictions sensible for products with only a few bonus periods.
+const BASE_RATE_WEIGHT: f64 = 1.0;
+
+pub struct WeekPrediction {
+    /// Monday of the week, AH bonus weeks run from Monday to Sunday
+    pub week_start: Date,
+    pub probability: f64,
+}
+
+/// Returns the Monday of the week after `date`.
+fn next_monday(date: Date) -> Date {
+    date + Duration::days(7 - date.weekday().number_days_from_monday() as i64)
+}
+
+fn weeks_between(start: Date, end: Date) -> i64 {
+    ((end - start).whole_days() as f64 / 7.0).round() as i64
+}
+
+/// Estimates the probability that a product is on bonus in each of the next `weeks`
+/// weeks, based on its past bonus periods.
+///
+/// Weeks covered by an already announced bonus period are certain. For the other
+/// weeks the estimate is the share of past intervals between bonus periods that
+/// ended in that week, among the intervals that lasted at least that long, blended
+/// with the share of recorded weeks the product was on bonus.
+pub fn predict_bonus_weeks(
+    periods: &[BonusPeriod],
+    first_recorded: Date,
+    today: Date,
+    weeks: usize,
+) -> Vec<WeekPrediction> {
+    let recorded_weeks = weeks_between(first_recorded, today).max(1);
+    let base_rate = (periods.len() as f64 / recorded_weeks as f64).min(1.0);
+
+    let intervals = periods
+        .windows(2)
+        .map(|pair| weeks_between(pair[0].start_date, pair[1].start_date))
+        .collect::<Vec<_>>();
+    let last_start = periods.last().map(|period| period.start_date);
+
+    let first_week = if today.weekday() == Weekday::Monday {
+        today
+    } else {
+        next_monday(today)
+    };
+    (0..weeks)
+        .map(|week| {
+            let week_start = first_week + Duration::weeks(week as i64);
+            let week_end = week_start + Duration::days(6);
+
+            let announced = periods
+                .iter()
+                .any(|period| period.start_date <= week_end && period.end_date >= week_start);
+            let probability = match (announced, last_start) {
+                (true, _) => 1.0,
+                (false, None) => base_rate,
+                (false, Some(last_start)) => {
+                    let since_last = weeks_between(last_start, week_start);
+                    let ended = intervals.iter().filter(|&&i| i == since_last).count() as f64;
+                    let lasted = intervals.iter().filter(|&&i| i >= since_last).count() as f64;
+                    (ended + base_rate * BASE_RATE_WEIGHT) / (lasted + BASE_RATE_WEIGHT)
+                }
+            };
+
+            WeekPrediction {
+                week_start,
+                probability: probability.clamp(0.0, 1.0),
+            }
+        })
+        .collect()
+}
+
+/// Returns the first upcoming week in which the product is more likely to be on
+/// bonus than not, using the same estimate as [`predict_bonus_weeks`].
+pub fn predict_next_bonus(
+    periods: &[BonusPeriod],
+    first_recorded: Date,
+    today: Date,
+) -> Option<WeekPrediction> {
+    predict_bonus_weeks(periods, first_recorded, today, NEXT_BONUS_WEEKS)
+        .into_iter()
+        .find(|week| week.probability >= 0.5)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use time::macros::date;
+
+    fn period(start_date: Date) -> BonusPeriod {
+        BonusPeriod {
+            start_date,
+            end_date: start_date + Duration::days(6),
+        }
+    }
+
+    /// A bonus every 4 weeks, the last one started 3 weeks before 19 October
+    fn every_four_weeks() -> Vec<BonusPeriod> {
+        [
+            date!(2026 - 07 - 06),
+            date!(2026 - 08 - 03),
+            date!(2026 - 08 - 31),
+            date!(2026 - 09 - 28),
+        ]
+        .into_iter()
+        .map(period)
+        .collect()
+    }
+
+    #[test]
+    fn empty_history_predicts_nothing() {
+        let weeks = predict_bonus_weeks(&[], date!(2026 - 10 - 19), date!(2026 - 10 - 19), 4);
+        assert_eq!(weeks.len(), 4);
+        assert!(weeks.iter().all(|week| week.probability == 0.0));
+        assert!(predict_next_bonus(&[], date!(2026 - 10 - 19), date!(2026 - 10 - 19)).is_none());
+    }
+
+    #[test]
+    fn never_on_bonus_uses_the_base_rate() {
+        let weeks = predict_bonus_weeks(&[], date!(2025 - 10 - 20), date!(2026 - 10 - 19), 4);
+        assert!(weeks.iter().all(|week| week.probability == 0.0));
+    }
+
+    #[test]
+    fn announced_bonus_is_certain() {
+        let periods = [period(date!(2026 - 10 - 26))];
+        let weeks = predict_bonus_weeks(&periods, date!(2026 - 01 - 05), date!(2026 - 10 - 19), 3);
+        assert_eq!(weeks[1].week_start, date!(2026 - 10 - 26));
+        assert_eq!(weeks[1].probability, 1.0);
+        assert!(weeks[0].probability < 1.0);
+        assert!(weeks[2].probability < 1.0);
+    }
+
+    #[test]
+    fn regular_cadence_predicts_the_next_bonus() {
+        let weeks = predict_bonus_weeks(
+            &every_four_weeks(),
+            date!(2026 - 07 - 06),
+            date!(2026 - 10 - 19),
+            4,
+        );
+        let probabilities = weeks
+            .iter()
+            .map(|week| week.probability)
+            .collect::<Vec<_>>();
+        // 3, 4, 5 and 6 weeks after the last bonus started
+        assert!(probabilities[0] < 0.5);
+        assert!(probabilities[1] > 0.8);
+        assert!(probabilities[2] < 0.5);
+        assert!(probabilities[3] < 0.5);
+
+        let next = predict_next_bonus(
+            &every_four_weeks(),
+            date!(2026 - 07 - 06),
+            date!(2026 - 10 - 19),
+        );
+        assert_eq!(
+            next.map(|week| week.week_start),
+            Some(date!(2026 - 10 - 26))
+        );
+    }
+
+    #[test]
+    fn weeks_start_on_the_next_monday() {
+        // on a Monday the current week is included
+        let weeks = predict_bonus_weeks(&[], date!(2026 - 01 - 05), date!(2026 - 10 - 19), 2);
+        assert_eq!(weeks[0].week_start, date!(2026 - 10 - 19));
+        assert_eq!(weeks[1].week_start, date!(2026 - 10 - 26));
+
+        for today in [
+            date!(2026 - 10 - 20),
+            date!(2026 - 10 - 21),
+            date!(2026 - 10 - 25),
+        ] {
+            let weeks = predict_bonus_weeks(&[], date!(2026 - 01 - 05), today, 1);
+            assert_eq!(
+                weeks[0].week_start,
+                date!(2026 - 10 - 26),
+                "today: {}",
+                today
+            );
+        }
+    }
+
+    #[test]
+    fn bonus_ending_before_the_first_week_is_not_announced() {
+        // the bonus of the current week ends on Sunday, before the weeks predicted
+        // on Wednesday
+        let periods = [period(date!(2026 - 10 - 19))];
+        let weeks = predict_bonus_weeks(&periods, date!(2026 - 01 - 05), date!(2026 - 10 - 21), 1);
+        assert_eq!(weeks[0].week_start, date!(2026 - 10 - 26));
+        assert!(weeks[0].probability < 1.0);
+
+        // a bonus starting on the Sunday of a week covers that week
+        let periods = [period(date!(2026 - 11 - 01))];
+        let weeks = predict_bonus_weeks(&periods, date!(2026 - 01 - 05), date!(2026 - 10 - 21), 1);
+        assert_eq!(weeks[0].probability, 1.0);
+    }
+}
@@ -0,0 +1,2 @@
+DELETE FROM tracked_products
+WHERE chat_id = ?1
@@ -0,0 +1,3 @@
+DELETE FROM taxonomy_watches
+WHERE id = ?1
+  AND chat_id = ?2
@@ -0,0 +1,16 @@
+-- products of inactive chats count as untracked, their prices are not fetched
+-- anymore. Products are kept until their last price is older than ?1 days, so
+-- tracking a product again soon keeps its history.
+DELETE FROM products
+WHERE id NOT IN (
+    SELECT tp.product_id
+    FROM tracked_products tp
+      LEFT JOIN chat_settings cs ON tp.chat_id = cs.chat_id
+    WHERE COALESCE(cs.active, TRUE)
+  )
+  AND NOT EXISTS (
+    SELECT 1
+    FROM products_history ph
+    WHERE ph.product_id = products.id
+      AND ph.created_at >= DATETIME('now', '-' || ?1 || ' days')
+  )
@@ -0,0 +1,3 @@
+DELETE FROM watches
+WHERE id = ?1
+  AND chat_id = ?2
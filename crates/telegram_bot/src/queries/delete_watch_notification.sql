@@ -0,0 +1,4 @@
+DELETE FROM watch_notifications
+WHERE chat_id = ?1
+  AND product_id = ?2
+  AND discount_end_date = ?3
@@ -0,0 +1,2 @@
+INSERT INTO brand_watches (chat_id, brand)
+VALUES (?1, ?2);
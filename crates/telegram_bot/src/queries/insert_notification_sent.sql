@@ -0,0 +1,8 @@
+INSERT INTO notifications_sent (
+    chat_id,
+    product_id,
+    discount_start_date,
+    discount_end_date,
+    kind
+  )
+VALUES (?1, ?2, ?3, ?4, ?5)
@@ -0,0 +1,2 @@
+INSERT INTO product_changes (product_id, kind, old_price, new_price)
+VALUES (?1, ?2, ?3, ?4)
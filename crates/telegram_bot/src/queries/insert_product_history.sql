@@ -3,6 +3,9 @@ INSERT INTO products_history (
     price,
     discount,
     discount_start_date,
-    discount_end_date
+    discount_end_date,
+    unit_size,
+    unit_price,
+    price_was
   )
-VALUES (?1, ?2, ?3, ?4, ?5);
+VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8);
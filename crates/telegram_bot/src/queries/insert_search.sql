@@ -0,0 +1,2 @@
+INSERT INTO searches (chat_id, query)
+VALUES (?1, ?2);
@@ -0,0 +1,9 @@
+INSERT INTO product_changes (
+    product_id,
+    kind,
+    old_price,
+    new_price,
+    old_unit_size,
+    new_unit_size
+  )
+VALUES (?1, 'shrinkflation', ?2, ?3, ?4, ?5)
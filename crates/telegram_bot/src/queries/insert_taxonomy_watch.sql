@@ -0,0 +1,2 @@
+INSERT INTO taxonomy_watches (chat_id, taxonomy_id, taxonomy_name)
+VALUES (?1, ?2, ?3);
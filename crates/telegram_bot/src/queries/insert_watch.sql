@@ -0,0 +1,2 @@
+INSERT INTO watches (chat_id, query)
+VALUES (?1, ?2);
@@ -0,0 +1,2 @@
+INSERT INTO watch_notifications (chat_id, product_id, discount_end_date)
+VALUES (?1, ?2, ?3);
@@ -0,0 +1,24 @@
+-- chats without settings get the defaults of `ChatSettings::new`, inactive chats
+-- are left out
+SELECT c.chat_id AS "chat_id!",
+  COALESCE(cs.notification_days, 16) AS "notification_days!: i64",
+  COALESCE(cs.notification_hour, 10) AS "notification_hour!: i64",
+  COALESCE(cs.send_nothing_on_sale, TRUE) AS "send_nothing_on_sale!: bool",
+  COALESCE(cs.last_day_reminder, FALSE) AS "last_day_reminder!: bool",
+  COALESCE(cs.digest, FALSE) AS "digest!: bool",
+  cs.last_notified_at AS "last_notified_at: PrimitiveDateTime"
+FROM (
+    SELECT chat_id
+    FROM tracked_products
+    UNION
+    SELECT chat_id
+    FROM watches
+    UNION
+    SELECT chat_id
+    FROM brand_watches
+    UNION
+    SELECT chat_id
+    FROM taxonomy_watches
+  ) c
+  LEFT JOIN chat_settings cs ON c.chat_id = cs.chat_id
+WHERE COALESCE(cs.active, TRUE)
@@ -0,0 +1,7 @@
+SELECT DISTINCT discount_start_date AS "start_date!: Date",
+  discount_end_date AS "end_date!: Date"
+FROM products_history
+WHERE product_id = ?1
+  AND discount_start_date IS NOT NULL
+  AND discount_end_date IS NOT NULL
+ORDER BY discount_start_date
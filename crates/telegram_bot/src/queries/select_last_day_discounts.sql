@@ -0,0 +1,45 @@
+SELECT DISTINCT ph.product_id,
+  ph.price,
+  ph.price_was,
+  ph.discount AS "discount!: String",
+  0 AS "future_discount!: i64",
+  ph.discount_start_date AS "discount_start_date!: Date",
+  ph.discount_end_date AS "discount_end_date!: Date",
+  tp.chat_id,
+  p.name,
+  p.url,
+  p.image_url
+FROM products_history ph
+  JOIN tracked_products tp ON ph.product_id = tp.product_id
+  JOIN products p ON ph.product_id = p.id
+WHERE ph.discount_end_date = ?1
+  AND tp.send_notification
+  AND (
+    tp.muted_until IS NULL
+    OR tp.muted_until <= DATE('now')
+  )
+  -- the target is compared with the bonus price, which is only known once the
+  -- bonus started: before that the price is the regular price
+  AND (
+    tp.target_price IS NULL
+    OR (
+      ph.discount_start_date <= DATE('now')
+      AND ph.price <= tp.target_price
+    )
+  )
+  -- bonuses without a regular price, e.g. 2 for 1, have no known discount
+  -- percentage and always pass the minimum discount
+  AND (
+    tp.min_discount IS NULL
+    OR ph.price_was IS NULL
+    OR (ph.price_was - ph.price) * 100 >= tp.min_discount * ph.price_was
+  )
+  AND NOT EXISTS (
+    SELECT 1
+    FROM notifications_sent ns
+    WHERE ns.chat_id = tp.chat_id
+      AND ns.product_id = ph.product_id
+      AND ns.discount_start_date = ph.discount_start_date
+      AND ns.discount_end_date = ph.discount_end_date
+      AND ns.kind = 'last_day'
+  )
@@ -0,0 +1,39 @@
+SELECT h.product_id AS "product_id!",
+  h.price AS "price!",
+  h.previous_price AS "previous_price!: i64",
+  h.lowest_previous_price AS "lowest_previous_price!: i64",
+  h.first_recorded_at AS "first_recorded_at!: PrimitiveDateTime",
+  h.created_at AS "recorded_at!: PrimitiveDateTime",
+  tp.chat_id,
+  p.name,
+  p.url,
+  p.image_url
+FROM (
+    SELECT product_id,
+      price,
+      created_at,
+      LAG(price) OVER w AS previous_price,
+      MIN(price) OVER (
+        w ROWS BETWEEN UNBOUNDED PRECEDING AND 1 PRECEDING
+      ) AS lowest_previous_price,
+      FIRST_VALUE(created_at) OVER w AS first_recorded_at
+    FROM products_history
+    WHERE discount IS NULL
+    WINDOW w AS (
+        PARTITION BY product_id
+        ORDER BY id
+      )
+  ) h
+  JOIN tracked_products tp ON h.product_id = tp.product_id
+  JOIN products p ON h.product_id = p.id
+WHERE h.price < h.previous_price
+  AND h.created_at >= ?1
+  AND tp.send_notification
+  AND (
+    tp.muted_until IS NULL
+    OR tp.muted_until <= DATE('now')
+  )
+  AND (
+    tp.target_price IS NULL
+    OR h.price <= tp.target_price
+  )
@@ -0,0 +1,7 @@
+SELECT DATE(created_at) AS "date!: Date",
+  price,
+  discount_start_date,
+  discount_end_date
+FROM products_history
+WHERE product_id = ?1
+ORDER BY id
@@ -0,0 +1,52 @@
+-- bonus periods are counted once, however often they were fetched, and only
+-- once they started
+WITH periods AS (
+  SELECT discount_start_date AS start_date,
+    discount_end_date AS end_date,
+    AVG(
+      CASE
+        WHEN price_was > 0 THEN (price_was - price) * 100.0 / price_was
+      END
+    ) AS discount
+  FROM products_history
+  WHERE product_id = ?1
+    AND discount_start_date IS NOT NULL
+    AND discount_end_date IS NOT NULL
+    AND discount_start_date <= DATE('now')
+  GROUP BY discount_start_date,
+    discount_end_date
+),
+-- the part of every period that falls within the last 52 weeks
+recent_periods AS (
+  SELECT MAX(start_date, DATE('now', '-364 days')) AS start_date,
+    MIN(end_date, DATE('now')) AS end_date
+  FROM periods
+  WHERE end_date >= DATE('now', '-364 days')
+)
+SELECT MIN(price) AS "min_price: i64",
+  MAX(price) AS "max_price: i64",
+  AVG(price) AS "average_price: f64",
+  (
+    SELECT MAX(end_date)
+    FROM periods
+  ) AS "last_bonus_date: Date",
+  (
+    SELECT MIN(
+        COALESCE(
+          SUM(
+            CAST(
+              (JULIANDAY(end_date) - JULIANDAY(start_date) + 7) / 7 AS INTEGER
+            )
+          ),
+          0
+        ),
+        52
+      )
+    FROM recent_periods
+  ) AS "bonus_weeks!: i64",
+  (
+    SELECT AVG(discount)
+    FROM periods
+  ) AS "average_discount: f64"
+FROM products_history
+WHERE product_id = ?1
@@ -0,0 +1,18 @@
+SELECT pc.product_id,
+  pc.kind,
+  pc.old_price,
+  pc.new_price,
+  pc.created_at AS "created_at: PrimitiveDateTime",
+  tp.chat_id,
+  p.name,
+  p.url
+FROM product_changes pc
+  JOIN tracked_products tp ON pc.product_id = tp.product_id
+  JOIN products p ON pc.product_id = p.id
+WHERE pc.kind IN ('price_increase', 'back_in_stock')
+  AND pc.created_at >= ?1
+  AND tp.send_notification
+  AND (
+    tp.muted_until IS NULL
+    OR tp.muted_until <= DATE('now')
+  )
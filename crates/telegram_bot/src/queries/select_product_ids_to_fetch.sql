@@ -0,0 +1,4 @@
+SELECT DISTINCT tp.product_id
+FROM tracked_products tp
+  LEFT JOIN chat_settings cs ON tp.chat_id = cs.chat_id
+WHERE COALESCE(cs.active, TRUE)
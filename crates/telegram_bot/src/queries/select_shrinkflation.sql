@@ -0,0 +1,13 @@
+SELECT pc.product_id,
+  pc.old_unit_size AS "previous_unit_size!: String",
+  pc.new_unit_size AS "unit_size!: String",
+  pc.old_price AS "previous_unit_price",
+  pc.new_price AS "unit_price",
+  pc.created_at AS "detected_at: PrimitiveDateTime",
+  p.name,
+  p.url
+FROM product_changes pc
+  JOIN products p ON pc.product_id = p.id
+WHERE pc.kind = 'shrinkflation'
+  AND pc.created_at >= ?1
+ORDER BY pc.created_at DESC
@@ -1,7 +1,28 @@
 SELECT p.id,
   p.name,
   p.url,
-  p.image_url
+  p.image_url,
+  ph.price AS "price?: i64",
+  COALESCE(
+    ph.discount_start_date <= DATE('now')
+    AND ph.discount_end_date >= DATE('now'),
+    FALSE
+  ) AS "on_bonus!: bool",
+  CASE
+    WHEN ph.discount_end_date >= DATE('now') THEN ph.discount
+  END AS "discount?: String",
+  ph.discount_start_date AS "discount_start_date?: Date",
+  ph.discount_end_date AS "discount_end_date?: Date",
+  ph.created_at AS "checked_at?: PrimitiveDateTime",
+  tp.send_notification,
+  CASE
+    WHEN tp.muted_until > DATE('now') THEN tp.muted_until
+  END AS "muted_until?: Date"
 FROM products p
   JOIN tracked_products tp ON p.id = tp.product_id
+  LEFT JOIN products_history ph ON ph.id = (
+    SELECT MAX(id)
+    FROM products_history
+    WHERE product_id = p.id
+  )
 WHERE tp.chat_id = ?1
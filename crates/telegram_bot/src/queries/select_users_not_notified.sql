@@ -1,8 +1,34 @@
 SELECT DISTINCT tp.chat_id
 FROM tracked_products tp
-WHERE tp.chat_id NOT IN (
+WHERE tp.send_notification
+  AND (
+    tp.muted_until IS NULL
+    OR tp.muted_until <= DATE('now')
+  )
+  AND tp.chat_id NOT IN (
     SELECT tp.chat_id
     FROM products_history ph
       JOIN tracked_products tp ON ph.product_id = tp.product_id
     WHERE ph.discount_end_date >= DATE('now')
+      AND tp.send_notification
+      AND (
+        tp.muted_until IS NULL
+        OR tp.muted_until <= DATE('now')
+      )
+      -- the target is compared with the bonus price, which is only known once the
+      -- bonus started: before that the price is the regular price
+      AND (
+        tp.target_price IS NULL
+        OR (
+          ph.discount_start_date <= DATE('now')
+          AND ph.price <= tp.target_price
+        )
+      )
+      -- bonuses without a regular price, e.g. 2 for 1, have no known discount
+      -- percentage and always pass the minimum discount
+      AND (
+        tp.min_discount IS NULL
+        OR ph.price_was IS NULL
+        OR (ph.price_was - ph.price) * 100 >= tp.min_discount * ph.price_was
+      )
   );
@@ -0,0 +1,12 @@
+-- chats without settings are stored with the defaults of `ChatSettings::new`
+INSERT INTO chat_settings (
+    chat_id,
+    notification_days,
+    notification_hour,
+    send_nothing_on_sale,
+    active
+  )
+VALUES (?1, 16, 10, TRUE, ?2) ON CONFLICT (chat_id) DO
+UPDATE
+SET active = excluded.active,
+  updated_at = CURRENT_TIMESTAMP
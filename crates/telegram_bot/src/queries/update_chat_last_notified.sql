@@ -0,0 +1,11 @@
+-- chats without settings are stored with the defaults of `ChatSettings::new`
+INSERT INTO chat_settings (
+    chat_id,
+    notification_days,
+    notification_hour,
+    send_nothing_on_sale,
+    last_notified_at
+  )
+VALUES (?1, 16, 10, TRUE, ?2) ON CONFLICT (chat_id) DO
+UPDATE
+SET last_notified_at = excluded.last_notified_at
@@ -0,0 +1,5 @@
+UPDATE tracked_products
+SET send_notification = ?3,
+  muted_until = ?4
+WHERE product_id = ?1
+  AND chat_id = ?2
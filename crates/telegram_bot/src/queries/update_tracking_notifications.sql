@@ -0,0 +1,4 @@
+UPDATE tracked_products
+SET send_notification = ?2,
+  muted_until = NULL
+WHERE chat_id = ?1
@@ -0,0 +1,5 @@
+UPDATE tracked_products
+SET target_price = ?3,
+  min_discount = ?4
+WHERE product_id = ?1
+  AND chat_id = ?2
@@ -0,0 +1,16 @@
+INSERT INTO chat_settings (
+    chat_id,
+    notification_days,
+    notification_hour,
+    send_nothing_on_sale,
+    last_day_reminder,
+    digest
+  )
+VALUES (?1, ?2, ?3, ?4, ?5, ?6) ON CONFLICT (chat_id) DO
+UPDATE
+SET notification_days = excluded.notification_days,
+  notification_hour = excluded.notification_hour,
+  send_nothing_on_sale = excluded.send_nothing_on_sale,
+  last_day_reminder = excluded.last_day_reminder,
+  digest = excluded.digest,
+  updated_at = CURRENT_TIMESTAMP
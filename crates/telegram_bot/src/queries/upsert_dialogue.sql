@@ -0,0 +1,5 @@
+INSERT INTO dialogues (chat_id, state)
+VALUES (?1, ?2) ON CONFLICT (chat_id) DO
+UPDATE
+SET state = excluded.state,
+  updated_at = CURRENT_TIMESTAMP
@@ -0,0 +1,5 @@
+INSERT INTO product_snapshots (product_id, snapshot)
+VALUES (?1, ?2) ON CONFLICT (product_id) DO
+UPDATE
+SET snapshot = excluded.snapshot,
+  updated_at = CURRENT_TIMESTAMP
@@ -0,0 +1,206 @@
+use time::{Date, Duration, Month, PrimitiveDateTime, Time, Weekday};
+
+/// Notification preferences of a chat. Times are in Dutch time, like the bonus
+/// weeks of AH.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ChatSettings {
+    pub chat_id: i64,
+    /// Days to get notified on, bit 0 is Monday and bit 6 is Sunday
+    pub notification_days: i64,
+    /// Hour of the day to get notified at, from 0 to 23
+    pub notification_hour: i64,
+    /// Whether to send a message when none of the tracked products are on sale
+    pub send_nothing_on_sale: bool,
+    /// Whether to send a reminder on the last day of a discount
+    pub last_day_reminder: bool,
+    /// Whether to get all discounts in one message instead of one message each
+    pub digest: bool,
+    /// When the chat was last notified, in UTC
+    pub last_notified_at: Option<PrimitiveDateTime>,
+}
+
+pub const WEEKDAYS: [Weekday; 7] = [
+    Weekday::Monday,
+    Weekday::Tuesday,
+    Weekday::Wednesday,
+    Weekday::Thursday,
+    Weekday::Friday,
+    Weekday::Saturday,
+    Weekday::Sunday,
+];
+
+impl ChatSettings {
+    /// Default settings, notify on Friday at 10:00. Keep in sync with the queries
+    /// that fill in settings of chats that never changed them.
+    pub fn new(chat_id: i64) -> Self {
+        Self {
+            chat_id,
+            notification_days: 1 << Weekday::Friday.number_days_from_monday(),
+            notification_hour: 10,
+            send_nothing_on_sale: true,
+            last_day_reminder: false,
+            digest: false,
+            last_notified_at: None,
+        }
+    }
+
+    pub fn has_day(&self, day: Weekday) -> bool {
+        self.notification_days & (1 << day.number_days_from_monday()) != 0
+    }
+
+    pub fn toggle_day(&mut self, day: Weekday) {
+        self.notification_days ^= 1 << day.number_days_from_monday();
+    }
+
+    /// Returns `true` if the chat should be notified at `now`, in UTC: the
+    /// notification time of today has passed and the chat wasn't notified since.
+    pub fn is_due(&self, now: PrimitiveDateTime) -> bool {
+        let now = to_dutch_time(now);
+        if !self.has_day(now.weekday()) || i64::from(now.hour()) < self.notification_hour {
+            return false;
+        }
+
+        let scheduled_at =
+            now.replace_time(Time::MIDNIGHT) + Duration::hours(self.notification_hour);
+        match self.last_notified_at {
+            Some(last_notified_at) => to_dutch_time(last_notified_at) < scheduled_at,
+            None => true,
+        }
+    }
+
+    /// Returns `true` if reminders of discounts ending today should be sent at
+    /// `now`, in UTC. Reminders are sent at the notification time on any day.
+    pub fn is_reminder_due(&self, now: PrimitiveDateTime) -> bool {
+        self.last_day_reminder && i64::from(to_dutch_time(now).hour()) >= self.notification_hour
+    }
+
+    /// Returns the days to get notified on, e.g. `Mon, Fri`
+    pub fn days_text(&self) -> String {
+        let days = WEEKDAYS
+            .into_iter()
+            .filter(|day| self.has_day(*day))
+            .map(weekday_abbreviation)
+            .collect::<Vec<_>>();
+        if days.is_empty() {
+            "none".to_string()
+        } else {
+            days.join(", ")
+        }
+    }
+}
+
+pub fn weekday_abbreviation(day: Weekday) -> &'static str {
+    match day {
+        Weekday::Monday => "Mon",
+        Weekday::Tuesday => "Tue",
+        Weekday::Wednesday => "Wed",
+        Weekday::Thursday => "Thu",
+        Weekday::Friday => "Fri",
+        Weekday::Saturday => "Sat",
+        Weekday::Sunday => "Sun",
+    }
+}
+
+/// Converts a time in UTC to the time in the Netherlands. Summer time starts and
+/// ends at 01:00 UTC on the last Sunday of March and October.
+pub fn to_dutch_time(utc: PrimitiveDateTime) -> PrimitiveDateTime {
+    let one_am = Time::from_hms(1, 0, 0).expect("valid time");
+    let summer_start = PrimitiveDateTime::new(last_sunday(utc.year(), Month::March), one_am);
+    let summer_end = PrimitiveDateTime::new(last_sunday(utc.year(), Month::October), one_am);
+
+    if (summer_start..summer_end).contains(&utc) {
+        utc + Duration::hours(2)
+    } else {
+        utc + Duration::hours(1)
+    }
+}
+
+fn last_sunday(year: i32, month: Month) -> Date {
+    let last_day = Date::from_calendar_date(year, month, month.length(year)).expect("valid date");
+    last_day - Duration::days(last_day.weekday().number_days_from_sunday().into())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use time::macros::datetime;
+
+    #[test]
+    fn winter_time_is_one_hour_ahead() {
+        assert_eq!(
+            to_dutch_time(datetime!(2026-01-15 12:00)),
+            datetime!(2026-01-15 13:00)
+        );
+    }
+
+    #[test]
+    fn summer_time_starts_on_last_sunday_of_march() {
+        // 29 March 2026, clocks go from 02:00 to 03:00
+        assert_eq!(
+            to_dutch_time(datetime!(2026-03-29 00:59)),
+            datetime!(2026-03-29 01:59)
+        );
+        assert_eq!(
+            to_dutch_time(datetime!(2026-03-29 01:00)),
+            datetime!(2026-03-29 03:00)
+        );
+    }
+
+    #[test]
+    fn summer_time_ends_on_last_sunday_of_october() {
+        // 25 October 2026, clocks go from 03:00 back to 02:00
+        assert_eq!(
+            to_dutch_time(datetime!(2026-10-25 00:59)),
+            datetime!(2026-10-25 02:59)
+        );
+        assert_eq!(
+            to_dutch_time(datetime!(2026-10-25 01:00)),
+            datetime!(2026-10-25 02:00)
+        );
+    }
+
+    #[test]
+    fn midnight_around_transitions() {
+        // the Dutch day starts before midnight UTC
+        assert_eq!(
+            to_dutch_time(datetime!(2026-03-28 23:00)),
+            datetime!(2026-03-29 00:00)
+        );
+        assert_eq!(
+            to_dutch_time(datetime!(2026-03-29 22:00)),
+            datetime!(2026-03-30 00:00)
+        );
+        assert_eq!(
+            to_dutch_time(datetime!(2026-10-24 22:00)),
+            datetime!(2026-10-25 00:00)
+        );
+        assert_eq!(
+            to_dutch_time(datetime!(2026-10-25 23:00)),
+            datetime!(2026-10-26 00:00)
+        );
+    }
+
+    #[test]
+    fn last_sunday_of_month() {
+        assert_eq!(
+            last_sunday(2026, Month::March),
+            Date::from_calendar_date(2026, Month::March, 29).unwrap()
+        );
+        // the last day of the month is a Sunday
+        assert_eq!(
+            last_sunday(2026, Month::May),
+            Date::from_calendar_date(2026, Month::May, 31).unwrap()
+        );
+    }
+
+    #[test]
+    fn due_at_notification_hour_in_dutch_time() {
+        let settings = ChatSettings::new(1);
+        // Friday 10:00 in summer is 08:00 UTC
+        assert!(!settings.is_due(datetime!(2026-10-23 07:59)));
+        assert!(settings.is_due(datetime!(2026-10-23 08:00)));
+        // Friday 10:00 in winter is 09:00 UTC
+        assert!(!settings.is_due(datetime!(2026-10-30 08:59)));
+        assert!(settings.is_due(datetime!(2026-10-30 09:00)));
+    }
+}
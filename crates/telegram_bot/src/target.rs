@@ -0,0 +1,54 @@
+use std::{fmt, str::FromStr};
+
+use serde::{Deserialize, Serialize};
+
+use crate::format::format_price;
+
+/// When a user wants to be notified about a discount on a tracked product.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum Target {
+    /// Notify about any discount, this is the default when tracking a product
+    AnyDiscount,
+    /// Notify only when the price in cents drops to or below this value
+    Price(i64),
+    /// Notify only when the discount is at least this percentage of the regular price
+    MinDiscount(i64),
+}
+
+impl FromStr for Target {
+    type Err = ();
+
+    /// Parses a target as typed by a user: `off`, a price like `2.50` or `€2,50`,
+    /// or a percentage like `25%`.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let s = s.trim();
+        if s.eq_ignore_ascii_case("off") || s.eq_ignore_ascii_case("any") {
+            return Ok(Target::AnyDiscount);
+        }
+
+        if let Some(percentage) = s.strip_suffix('%') {
+            return match percentage.trim().parse::<i64>() {
+                Ok(percentage) if (1..=100).contains(&percentage) => {
+                    Ok(Target::MinDiscount(percentage))
+                }
+                _ => Err(()),
+            };
+        }
+
+        let price = s.trim_start_matches('€').trim().replace(',', ".");
+        match price.parse::<f64>() {
+            Ok(price) if price > 0.0 => Ok(Target::Price((price * 100.0).round() as i64)),
+            _ => Err(()),
+        }
+    }
+}
+
+impl fmt::Display for Target {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Target::AnyDiscount => write!(f, "any discount"),
+            Target::Price(cents) => write!(f, "{} or less", format_price(*cents)),
+            Target::MinDiscount(percentage) => write!(f, "at least {}% off", percentage),
+        }
+    }
+}
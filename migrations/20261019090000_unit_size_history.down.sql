@@ -0,0 +1,2 @@
+ALTER TABLE products_history DROP COLUMN unit_price;
+ALTER TABLE products_history DROP COLUMN unit_size;
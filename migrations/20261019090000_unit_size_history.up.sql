@@ -0,0 +1,4 @@
+ALTER TABLE products_history
+ADD COLUMN unit_size TEXT;
+ALTER TABLE products_history
+ADD COLUMN unit_price INTEGER;
@@ -0,0 +1,3 @@
+ALTER TABLE products_history DROP COLUMN price_was;
+ALTER TABLE tracked_products DROP COLUMN min_discount;
+ALTER TABLE tracked_products DROP COLUMN target_price;
@@ -0,0 +1,6 @@
+ALTER TABLE tracked_products
+ADD COLUMN target_price INTEGER;
+ALTER TABLE tracked_products
+ADD COLUMN min_discount INTEGER;
+ALTER TABLE products_history
+ADD COLUMN price_was INTEGER;
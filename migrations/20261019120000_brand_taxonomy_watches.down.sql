@@ -0,0 +1,2 @@
+DROP TABLE IF EXISTS taxonomy_watches;
+DROP TABLE IF EXISTS brand_watches;
@@ -0,0 +1,15 @@
+CREATE TABLE IF NOT EXISTS brand_watches (
+  id INTEGER PRIMARY KEY AUTOINCREMENT,
+  chat_id INTEGER NOT NULL,
+  brand TEXT NOT NULL,
+  created_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP NOT NULL,
+  UNIQUE (chat_id, brand)
+);
+CREATE TABLE IF NOT EXISTS taxonomy_watches (
+  id INTEGER PRIMARY KEY AUTOINCREMENT,
+  chat_id INTEGER NOT NULL,
+  taxonomy_id INTEGER NOT NULL,
+  taxonomy_name TEXT NOT NULL,
+  created_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP NOT NULL,
+  UNIQUE (chat_id, taxonomy_id)
+);
@@ -0,0 +1,5 @@
+CREATE TABLE IF NOT EXISTS dialogues (
+  chat_id INTEGER PRIMARY KEY NOT NULL,
+  state TEXT NOT NULL,
+  updated_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP NOT NULL
+);
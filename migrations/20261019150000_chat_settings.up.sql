@@ -0,0 +1,8 @@
+CREATE TABLE IF NOT EXISTS chat_settings (
+  chat_id INTEGER PRIMARY KEY NOT NULL,
+  notification_days INTEGER NOT NULL,
+  notification_hour INTEGER NOT NULL,
+  send_nothing_on_sale BOOLEAN NOT NULL,
+  last_notified_at TIMESTAMP,
+  updated_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP NOT NULL
+);
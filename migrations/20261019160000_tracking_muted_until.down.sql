@@ -0,0 +1 @@
+ALTER TABLE tracked_products DROP COLUMN muted_until;
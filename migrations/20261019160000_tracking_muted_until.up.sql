@@ -0,0 +1,2 @@
+ALTER TABLE tracked_products
+ADD COLUMN muted_until DATE;
@@ -0,0 +1,2 @@
+ALTER TABLE chat_settings DROP COLUMN last_day_reminder;
+DROP TABLE IF EXISTS notifications_sent;
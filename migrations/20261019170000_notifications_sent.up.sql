@@ -0,0 +1,19 @@
+CREATE TABLE IF NOT EXISTS notifications_sent (
+  id INTEGER PRIMARY KEY AUTOINCREMENT,
+  chat_id INTEGER NOT NULL,
+  product_id INTEGER NOT NULL,
+  discount_start_date DATE NOT NULL,
+  discount_end_date DATE NOT NULL,
+  -- 'bonus' when the discount is announced, 'last_day' for the reminder
+  kind TEXT NOT NULL,
+  created_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP NOT NULL,
+  UNIQUE (
+    chat_id,
+    product_id,
+    discount_start_date,
+    discount_end_date,
+    kind
+  )
+);
+ALTER TABLE chat_settings
+ADD COLUMN last_day_reminder BOOLEAN DEFAULT FALSE NOT NULL;
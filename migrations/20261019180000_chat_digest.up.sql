@@ -0,0 +1,2 @@
+ALTER TABLE chat_settings
+ADD COLUMN digest BOOLEAN DEFAULT FALSE NOT NULL;
@@ -0,0 +1,2 @@
+ALTER TABLE chat_settings
+ADD COLUMN active BOOLEAN DEFAULT TRUE NOT NULL;
@@ -0,0 +1,2 @@
+DROP TABLE IF EXISTS product_changes;
+DROP TABLE IF EXISTS product_snapshots;
@@ -0,0 +1,17 @@
+CREATE TABLE IF NOT EXISTS product_snapshots (
+  product_id INTEGER PRIMARY KEY NOT NULL,
+  -- last fetched product as returned by the AH API, in JSON
+  snapshot TEXT NOT NULL,
+  updated_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP NOT NULL,
+  FOREIGN KEY(product_id) REFERENCES products(id) ON DELETE CASCADE
+);
+CREATE TABLE IF NOT EXISTS product_changes (
+  id INTEGER PRIMARY KEY AUTOINCREMENT,
+  product_id INTEGER NOT NULL,
+  -- 'price_increase' or 'back_in_stock'
+  kind TEXT NOT NULL,
+  old_price INTEGER NOT NULL,
+  new_price INTEGER NOT NULL,
+  created_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP NOT NULL,
+  FOREIGN KEY(product_id) REFERENCES products(id) ON DELETE CASCADE
+);
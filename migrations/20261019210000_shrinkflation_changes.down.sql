@@ -0,0 +1,4 @@
+DELETE FROM product_changes
+WHERE kind = 'shrinkflation';
+ALTER TABLE product_changes DROP COLUMN new_unit_size;
+ALTER TABLE product_changes DROP COLUMN old_unit_size;
@@ -0,0 +1,38 @@
+-- shrinkflation is recorded as a product change like price increases, with the
+-- price per unit in old_price and new_price
+ALTER TABLE product_changes
+ADD COLUMN old_unit_size TEXT;
+ALTER TABLE product_changes
+ADD COLUMN new_unit_size TEXT;
+-- keep the shrinkflation found in the price history so far
+INSERT INTO product_changes (
+    product_id,
+    kind,
+    old_price,
+    new_price,
+    old_unit_size,
+    new_unit_size,
+    created_at
+  )
+SELECT product_id,
+  'shrinkflation',
+  previous_unit_price,
+  unit_price,
+  previous_unit_size,
+  unit_size,
+  created_at
+FROM (
+    SELECT product_id,
+      unit_size,
+      unit_price,
+      created_at,
+      LAG(unit_size) OVER w AS previous_unit_size,
+      LAG(unit_price) OVER w AS previous_unit_price
+    FROM products_history
+    WINDOW w AS (
+        PARTITION BY product_id
+        ORDER BY id
+      )
+  )
+WHERE unit_size <> previous_unit_size
+  AND unit_price > previous_unit_price;